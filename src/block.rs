@@ -2,13 +2,19 @@ use serde::{Serialize, Deserialize};
 use sha2::{Sha256, Digest};
 use chrono::Utc;
 use crate::transaction::Transaction; // Import Transaction
+use crate::quantum::QuantumWallet;
+use crate::merkle::merkle_root;
+use crate::snn_core::neural_work;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Block {
     pub index: u64,
+    pub slot: u64,
     pub timestamp: i64,
     pub prev_hash: String,
     pub hash: String,
+    pub state_root: String,
+    pub tx_root: String,
     pub transactions: Vec<Transaction>, // S?a t? Vec<String> thành Vec<Transaction>
     pub spike_score: f32,
     pub miner: String,
@@ -16,10 +22,23 @@ pub struct Block {
     pub pqc_signature: String,
 }
 
+/// Phần đầu block gửi trong giao thức sync (không kèm giao dịch).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockHeader {
+    pub index: u64,
+    pub hash: String,
+    pub prev_hash: String,
+    pub tx_root: String,
+    pub spike_score: f32,
+}
+
 impl Block {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         index: u64, 
+        slot: u64,
         prev_hash: String, 
+        state_root: String,
         transactions: Vec<Transaction>, 
         spike_score: f32, 
         miner: String,
@@ -28,9 +47,12 @@ impl Block {
         let timestamp = Utc::now().timestamp();
         let mut block = Self {
             index,
+            slot,
             timestamp,
             prev_hash,
             hash: String::new(),
+            state_root,
+            tx_root: merkle_root(&transactions),
             transactions,
             spike_score,
             miner,
@@ -42,20 +64,61 @@ impl Block {
     }
 
     pub fn calculate_hash(&self) -> String {
-        // Hash danh sách transaction ID
-        let tx_data = self.transactions.iter().map(|t| t.id.clone()).collect::<String>();
-        
-        let input = format!("{}{}{}{}{}{}{}", 
+        let input = format!("{}:{}{}{}{}{}{}{}{}", 
             self.index, 
+            self.slot, 
             self.timestamp, 
             self.prev_hash, 
+            self.state_root, 
             self.spike_score, 
             self.miner,
             self.miner_pqc_pubkey,
-            tx_data
+            self.tx_root
         );
         let mut hasher = Sha256::new();
         hasher.update(input.as_bytes());
         hex::encode(hasher.finalize())
     }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader {
+            index: self.index,
+            hash: self.hash.clone(),
+            prev_hash: self.prev_hash.clone(),
+            tx_root: self.tx_root.clone(),
+            spike_score: self.spike_score,
+        }
+    }
+
+    /// Kiểm tra nội tại của block nhận từ peer: hash, chữ ký Dilithium của miner, spike_score chạy lại được
+    /// và chữ ký từng giao dịch.
+    pub fn verify_seal(&self) -> Result<(), String> {
+        if self.tx_root != merkle_root(&self.transactions) {
+            return Err(format!("Block #{} sai tx_root", self.index));
+        }
+        if self.hash != self.calculate_hash() {
+            return Err(format!("Block #{} sai hash", self.index));
+        }
+        let pub_key = hex::decode(&self.miner_pqc_pubkey).map_err(|_| "miner_pqc_pubkey không hợp lệ".to_string())?;
+        let signature = hex::decode(&self.pqc_signature).map_err(|_| "pqc_signature không hợp lệ".to_string())?;
+        if !QuantumWallet::verify_data(self.hash.as_bytes(), &signature, &pub_key) {
+            return Err(format!("Block #{} sai chữ ký Dilithium", self.index));
+        }
+        if self.spike_score != neural_work(&self.prev_hash, &self.miner_pqc_pubkey, self.slot) {
+            return Err(format!("Block #{} sai spike_score (proof-of-neural-work)", self.index));
+        }
+        let coinbase_count = self.transactions.iter().take_while(|tx| tx.is_coinbase()).count();
+        for (i, tx) in self.transactions.iter().enumerate() {
+            if tx.is_coinbase() && i >= coinbase_count {
+                return Err(format!("Block #{}: coinbase không đứng đầu", self.index));
+            }
+            if tx.id != tx.calculate_hash() || (!tx.is_coinbase() && !tx.verify()) {
+                return Err(format!("Giao dịch {} không hợp lệ", tx.id));
+            }
+            if !tx.is_coinbase() {
+                tx.check_payload().map_err(|e| format!("Giao dịch {}: {}", tx.id, e))?;
+            }
+        }
+        Ok(())
+    }
 }
@@ -39,7 +39,7 @@ impl SmartCache {
         });
     }
 
-    // Dọn dẹp bộ nhớ định kỳ
+    // Dọn dẹp bộ nhớ định kỳ (mục hết hạn không còn trả về nhưng vẫn chiếm RAM tới khi bị xóa)
     pub async fn prune(&self) {
         let mut store = self.store.write().await;
         let now = Instant::now();
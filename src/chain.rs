@@ -1,105 +1,746 @@
-use crate::{snn_core::SNNCore, block::Block, storage::Storage, quantum::QuantumWallet, cache::SmartCache, p2p::P2PNode, transaction::Mempool};
+use crate::{snn_core::{SNNCore, neural_work}, block::Block, storage::ChainStore, genesis::ChainSpec, quantum::QuantumWallet, keystore::NodeIdentity, cache::SmartCache, p2p::{P2PHandle, NetEvent}, transaction::{Mempool, Transaction, TxPayload, TxReject}, governance::{NeuroDAO, VoteChoice}, params::{self, ChainParams}, snapshot::PruneConfig, state::StateView, wallet::address_from_pubkey, webnode::{self, WEB_REWARD_PERCENT, MAX_PAYOUTS_PER_BLOCK}, ethics::EthicsFilter, sync::{SyncManager, SyncRequest, SyncResponse}, consensus::{Schedule, block_work}, finality::{FinalityGadget, Vote, VoteOutcome}};
 use std::sync::Arc;
-use tokio::sync::{RwLock, Mutex};
-use std::collections::VecDeque;
+use tokio::sync::{RwLock, Mutex, mpsc};
+use std::collections::{VecDeque, HashMap, HashSet, BTreeMap};
 use std::io::{self, Write};
-use std::time::Instant;
-use bincode;
+use libp2p::gossipsub::MessageAcceptance;
+
+const MAX_ORPHANS: usize = 256;
+
+/// Kết quả khi đưa một block vào cây block.
+#[derive(Debug)]
+pub enum ImportOutcome {
+    Extended,
+    Reorged { depth: u64 },
+    SideChain,
+    Orphan,
+}
 
 pub struct PappapChain {
     pub snn: Arc<SNNCore>,
     pub height: Arc<RwLock<u64>>,
     pub last_hash: Arc<RwLock<String>>,
-    pub storage: Arc<Storage>,
+    pub storage: Arc<dyn ChainStore>,
     pub blocks_history: Arc<RwLock<VecDeque<Block>>>,
     pub wallet: Arc<QuantumWallet>,
-    pub p2p_node: Arc<Mutex<P2PNode>>,
+    pub identity: NodeIdentity,
+    /// Địa chỉ nhận phần thưởng block và phí (PAPPAP_REWARD_ADDRESS).
+    pub reward_address: String,
+    pub p2p: P2PHandle,
     pub mempool: Arc<Mempool>,
+    pub sync: SyncManager,
+    pub spec: ChainSpec,
+    pub schedule: Schedule,
+    pub finality: FinalityGadget,
+    pub genesis_hash: String,
+    /// Chế độ prune block cũ và chụp snapshot định kỳ.
+    pub prune: PruneConfig,
+    // Khóa đỉnh chuỗi: block tự đào và block nhận từ peer không được ghi chen nhau
+    tip_lock: Mutex<()>,
+    // Block chưa có cha, gom theo prev_hash để nối khi cha tới
+    orphans: Mutex<HashMap<String, Vec<Block>>>,
+    // Lỗi làm trạng thái không còn tin được (vd. không khôi phục được chuỗi cũ sau reorg hỏng):
+    // node ngừng đề xuất và nhận block, /api/health báo không khỏe
+    fault: std::sync::Mutex<Option<String>>,
+}
+
+// Phần web của block: WEB_REWARD_PERCENT phần thưởng trả cho web worker của epoch trước (`webnode::settle`),
+// epoch trước không ai đóng góp thì producer nhận cả. Trả về (khoản trả web worker, số rút khỏi quỹ thưởng);
+// phần web chưa trả hết vẫn nằm trong quỹ.
+fn web_payouts(view: &mut StateView, reward: u64) -> (BTreeMap<String, u64>, u64) {
+    let share = reward * WEB_REWARD_PERCENT / 100;
+    match webnode::settle(view, share) {
+        Some(payouts) => {
+            let paid: u64 = payouts.values().sum();
+            (payouts, reward - share + paid)
+        }
+        None => (BTreeMap::new(), reward),
+    }
 }
 
 impl PappapChain {
     // S?A L?I: Nh?n d? 3 tham s? d? kh?p v?i main.rs
-    pub async fn new(storage: Arc<Storage>, cache: SmartCache, p2p_node: Arc<Mutex<P2PNode>>) -> Self {
+    pub async fn new(storage: Arc<dyn ChainStore>, cache: SmartCache, p2p: P2PHandle, spec: ChainSpec, wallet: QuantumWallet, identity: NodeIdentity) -> Self {
+        // Chuỗi khác chain spec được giữ nguyên, không đè lên
+        let genesis = spec.genesis_block();
+        match storage.get_block(0) {
+            Some(b) if b.hash != genesis.hash => panic!("Genesis trong DB ({}) khác chain spec ({}): dùng thư mục dữ liệu khác hoặc xóa pappap_data", b.hash, genesis.hash),
+            None if storage.get_height() > 0 => panic!("DB chứa chuỗi cũ chưa có block genesis: dùng thư mục dữ liệu khác hoặc xóa pappap_data"),
+            _ => {}
+        }
+        if let Err(e) = storage.check_consistency() { panic!("{}", e); }
+        if storage.get_block(0).is_none() { storage.init_genesis(&genesis, &spec.genesis_accounts(), &spec.genesis_data()); }
+        println!("?? GENESIS: {} | Chain: {}", genesis.hash, spec.chain_id);
+
         let saved_height = storage.get_height();
         let saved_hash = storage.get_last_hash();
-        let wallet = Arc::new(QuantumWallet::new());
-        
+        let supply: u128 = storage.load_accounts().iter().map(|(_, a)| a.balance as u128 + a.staked as u128).sum();
+        let released = params::emitted(&*storage).unwrap_or_else(|e| panic!("{}", e));
+        if supply != spec.genesis_supply() || released > spec.released_at(saved_height) {
+            panic!("Sai bất biến tổng cung tại height {}: ledger {}, genesis {}, đã trả {}, trần lịch thưởng {}", saved_height, supply, spec.genesis_supply(), released, spec.released_at(saved_height));
+        }
+        let wallet = Arc::new(wallet);
+        let schedule = Schedule::from_spec(&spec);
+        if let Some(v) = spec.validators.iter().find(|v| v.pqc_pubkey == identity.pqc_pubkey) {
+            if v.name != identity.name {
+                println!("⚠️  Tên node '{}' khác tên validator '{}' trong chain spec, block sẽ mang tên '{}'", identity.name, v.name, v.name);
+            }
+        }
+        let reward_address = std::env::var("PAPPAP_REWARD_ADDRESS").unwrap_or_else(|_| {
+            println!("⚠️  Chưa đặt PAPPAP_REWARD_ADDRESS, phần thưởng sẽ về địa chỉ suy từ khóa Dilithium");
+            address_from_pubkey(&wallet.public_key)
+        });
+        println!("?? IDENTITY: {} | Peer: {} | PQC: {}...", identity.name, identity.peer_id, &identity.pqc_pubkey[..16]);
+
         // S?A L?I: Truy?n d? storage và cache vào SNNCore
         let snn = Arc::new(SNNCore::new(storage.clone(), cache));
-        
-        let mempool = Arc::new(Mempool::new());
+
+        let mempool = Arc::new(Mempool::from_env(storage.clone()));
+        let (kept, dropped) = mempool.reload(|addr| storage.get_account(addr).unwrap_or_default());
+        println!("?? MEMPOOL: Nạp lại {} giao dịch, bỏ {} (hết hạn/không còn hợp lệ)", kept, dropped);
 
         println!("?? CHAIN SYNCED | Height: {}", saved_height);
+        let prune = PruneConfig::from_env();
+        if prune.keep_blocks > 0 {
+            println!("?? PRUNE: Giữ {} block gần nhất (đã prune tới #{})", prune.keep_blocks, storage.get_pruned_height());
+        }
+        if prune.snapshot_interval > 0 {
+            println!("?? SNAPSHOT: Mỗi {} block vào {}/", prune.snapshot_interval, prune.snapshot_dir);
+        }
 
-        Self {
+        let chain = Self {
             snn,
             height: Arc::new(RwLock::new(saved_height)),
             last_hash: Arc::new(RwLock::new(saved_hash)),
             blocks_history: Arc::new(RwLock::new(VecDeque::with_capacity(50))),
             storage,
             wallet,
-            p2p_node,
+            p2p,
             mempool,
+            sync: SyncManager::new(),
+            genesis_hash: genesis.hash,
+            schedule,
+            identity,
+            reward_address,
+            finality: FinalityGadget::new(),
+            prune,
+            spec,
+            tip_lock: Mutex::new(()),
+            orphans: Mutex::new(HashMap::new()),
+            fault: std::sync::Mutex::new(None),
+        };
+        chain.refresh_params();
+        chain
+    }
+
+    /// Lỗi nghiêm trọng đã làm node dừng ghi chuỗi, `None` khi node khỏe.
+    pub fn fault(&self) -> Option<String> {
+        self.fault.lock().unwrap().clone()
+    }
+
+    fn set_fault(&self, fault: &str) {
+        println!("\n❌ NODE: {}. Ngừng đề xuất và nhận block, cần khởi động lại", fault);
+        self.fault.lock().unwrap().get_or_insert_with(|| fault.to_string());
+    }
+
+    /// Tham số chuỗi sau block đỉnh (DB đã được kiểm lúc mở; nếu vẫn đọc lỗi thì tạm dùng tham số genesis).
+    pub fn params(&self) -> ChainParams {
+        ChainParams::current(&*self.storage).unwrap_or_else(|e| {
+            println!("\n⚠️  PARAMS: {}", e);
+            ChainParams::initial(&self.spec)
+        })
+    }
+
+    // Đồng bộ lịch slot và phí tối thiểu của mempool với tham số của đỉnh chuỗi mới
+    fn refresh_params(&self) {
+        let params = self.params();
+        self.schedule.set_timing(params.timing);
+        self.mempool.set_min_fee(params.min_fee);
+    }
+
+    // Áp giao dịch của block lên ledger (coinbase áp sau cùng), chốt đề xuất DAO và kích hoạt thay đổi tham số
+    // tới hạn, đối chiếu state_root và tổng cung rồi ghi vào chuỗi chính (gọi khi đang giữ tip_lock)
+    async fn commit_block(&self, block: &Block) -> Result<(), String> {
+        let mut view = StateView::new(&*self.storage, block.index, &self.spec.governance);
+        let params = ChainParams::load(&view)?;
+        // Coinbase: tối đa MAX_PAYOUTS_PER_BLOCK web worker + producer
+        if block.transactions.len() > params.max_block_txs as usize + MAX_PAYOUTS_PER_BLOCK + 1 {
+            return Err(format!("Block #{} có quá nhiều giao dịch", block.index));
+        }
+        let (coinbases, txs) = block.transactions.split_at(block.transactions.iter().take_while(|tx| tx.is_coinbase()).count());
+        if coinbases.is_empty() {
+            return Err(format!("Block #{} thiếu coinbase", block.index));
+        }
+        let outputs: Vec<(&str, u64)> = coinbases.iter().filter_map(|tx| match &tx.payload {
+            TxPayload::Coinbase { receiver, amount } => Some((receiver.as_str(), *amount)),
+            _ => None,
+        }).collect();
+        let receivers: HashSet<&str> = outputs.iter().map(|(r, _)| *r).collect();
+        if receivers.len() != coinbases.len() || coinbases.iter().any(|tx| tx.nonce != block.index) {
+            return Err(format!("Block #{}: coinbase trùng người nhận hoặc sai nonce", block.index));
+        }
+        let reward = params.block_reward(&self.spec, block.index);
+        let fees = txs.iter().try_fold(0u64, |acc, tx| acc.checked_add(tx.fee)).ok_or("Tràn tổng phí")?;
+        let paid = outputs.iter().try_fold(0u64, |acc, (_, amount)| acc.checked_add(*amount)).ok_or("Tràn tổng coinbase")?;
+        webnode::anchor_epoch(&mut view, &block.prev_hash);
+        for tx in txs {
+            view.apply_tx(tx).map_err(|e| format!("Block #{}: giao dịch {} lỗi: {}", block.index, tx.id, e))?;
+        }
+        // Khoản trả web worker tính lại từ đóng góp đã ghi trên chuỗi: mỗi worker nhận đúng phần của mình,
+        // chỉ một coinbase (của producer) được khác phần web, tổng khớp phần rút từ quỹ thưởng + phí
+        let (web, released) = web_payouts(&mut view, reward);
+        if Some(paid) != released.checked_add(fees) {
+            return Err(format!("Block #{}: coinbase {} khác phần thưởng {} + phí {}", block.index, paid, released, fees));
+        }
+        let amounts: HashMap<&str, u64> = outputs.iter().copied().collect();
+        if web.iter().any(|(addr, amount)| amounts.get(addr.as_str()).is_none_or(|paid| paid < amount))
+            || outputs.iter().filter(|(addr, amount)| web.get(*addr) != Some(amount)).count() > 1 {
+            return Err(format!("Block #{}: coinbase trả web worker sai đóng góp đã ghi trên chuỗi", block.index));
+        }
+        for tx in coinbases { view.apply_coinbase(tx)?; }
+        let closed = NeuroDAO::close_ended(&mut view);
+        let executed = NeuroDAO::activate_due(&mut view, block.slot)?;
+        view.release_reward(&self.spec.emission.reward_pool, released).map_err(|e| format!("Block #{}: {}", block.index, e))?;
+        params::add_emitted(&mut view, released)?;
+        if view.root() != block.state_root {
+            return Err(format!("Block #{} sai state_root", block.index));
+        }
+        let diff = view.into_diff();
+        if diff.supply_delta() != 0 {
+            return Err(format!("Block #{} vi phạm bất biến tổng cung", block.index));
+        }
+        self.storage.commit_block(block, &diff);
+        self.prune.on_commit(&*self.storage, &self.spec, block.index);
+        for p in closed {
+            println!("\n?? DAO: Đề xuất #{} '{}' {:?} (yes {} / no {} / tổng stake {})", p.id, p.title, p.status, p.tally.yes, p.tally.no, p.tally.total_staked);
+        }
+        for p in &executed {
+            println!("\n?? DAO: Áp dụng đề xuất #{} tại block #{}: {:?}", p.id, block.index, p.action);
+        }
+        if !executed.is_empty() { self.refresh_params(); }
+        self.mempool.remove_included(&block.transactions);
+        *self.last_hash.write().await = block.hash.clone();
+        *self.height.write().await = block.index;
+
+        let mut history = self.blocks_history.write().await;
+        if history.len() >= 15 { history.pop_front(); }
+        history.push_back(block.clone());
+        Ok(())
+    }
+
+    // Gỡ chuỗi chính về block `index` (hoàn tác trạng thái các block phía trên)
+    async fn rewind(&self, index: u64, hash: &str) {
+        let height = *self.height.read().await;
+        for i in ((index + 1)..=height).rev() {
+            if let Some(b) = self.storage.get_block(i) { self.storage.disconnect_tip(&b); }
+        }
+        self.refresh_params();
+        *self.height.write().await = index;
+        *self.last_hash.write().await = hash.to_string();
+        self.blocks_history.write().await.retain(|b| b.index <= index);
+    }
+
+    // Coinbase của block tự đào (gọi sau khi đã áp giao dịch lên `view`): khoản trả web worker theo `web_payouts`,
+    // phần thưởng còn lại cùng toàn bộ phí về reward_address. Trả về kèm số rút từ quỹ thưởng.
+    fn build_coinbases(&self, view: &mut StateView, reward: u64, fees: u64) -> (Vec<Transaction>, u64) {
+        let (mut payouts, released) = web_payouts(view, reward);
+        let web: u64 = payouts.values().sum();
+        *payouts.entry(self.reward_address.clone()).or_insert(0) += released - web + fees;
+        let timestamp = chrono::Utc::now().timestamp();
+        let coinbases = payouts.into_iter()
+            .filter(|(addr, amount)| *amount > 0 || *addr == self.reward_address)
+            .map(|(addr, amount)| Transaction::coinbase(view.height, &addr, amount, timestamp))
+            .collect();
+        (coinbases, released)
+    }
+
+    /// Đưa giao dịch vào mempool sau khi đối chiếu với trạng thái tài khoản người gửi (và governance với phiếu DAO).
+    pub fn submit_tx(&self, tx: Transaction) -> Result<(), TxReject> {
+        let sender = tx.sender_address().ok_or(TxReject::InvalidSender)?;
+        let account = self.storage.get_account(&sender).unwrap_or_default();
+        let blacklist = self.params().ethics_blacklist;
+        for text in tx.payload.texts() {
+            EthicsFilter::check_with(text, &blacklist).map_err(|error| TxReject::InvalidPayload { error })?;
+        }
+        // Giao dịch governance không thể vào block (đề xuất không tồn tại/đã đóng, phiếu trùng, ủy quyền sai)
+        // sẽ chặn nonce của người gửi
+        let mut view = StateView::new(&*self.storage, self.storage.get_height() + 1, &self.spec.governance);
+        webnode::anchor_epoch(&mut view, &self.storage.get_last_hash());
+        let check = match &tx.payload {
+            TxPayload::Vote { proposal_id, approve } => NeuroDAO::vote(&mut view, &sender, *proposal_id, VoteChoice::from_approve(*approve)),
+            TxPayload::CastVote { proposal_id, choice } => NeuroDAO::vote(&mut view, &sender, *proposal_id, *choice),
+            TxPayload::Delegate { delegate } => NeuroDAO::delegate(&mut view, &sender, delegate),
+            TxPayload::Undelegate => NeuroDAO::undelegate(&mut view, &sender),
+            _ => Ok(()),
+        };
+        check.map_err(|error| TxReject::GovernanceRejected { error })?;
+        // Bằng chứng công hết hạn, đã dùng hoặc sai lời giải cũng chặn nonce của người gửi
+        if let TxPayload::WebWork { epoch, worker, index, answer } = &tx.payload {
+            webnode::record_work(&mut view, *epoch, worker, *index, *answer).map_err(|error| TxReject::WorkRejected { error })?;
+        }
+        self.mempool.add_tx(tx, &account)
+    }
+
+    /// Nhận block từ peer: kiểm tra chữ ký rồi đưa vào cây block theo luật fork choice.
+    pub async fn import_block(&self, block: Block) -> Result<ImportOutcome, String> {
+        if self.storage.get_block_by_hash(&block.hash).is_some() {
+            return Err(format!("Block #{} đã có", block.index));
+        }
+        if let Some(fault) = self.fault() {
+            return Err(format!("Node đang lỗi, không nhận block: {}", fault));
+        }
+        block.verify_seal()?;
+
+        let tip = self.tip_lock.lock().await;
+        let hash = block.hash.clone();
+        let outcome = self.connect_block(block).await?;
+        if !matches!(outcome, ImportOutcome::Orphan) {
+            self.connect_orphans(hash).await;
+        }
+        drop(tip);
+        if matches!(outcome, ImportOutcome::Extended | ImportOutcome::Reorged { .. }) {
+            self.finality.on_new_tip(self).await;
+        }
+        Ok(outcome)
+    }
+
+    // Fork choice (PoA): nhánh dài nhất (nhiều block hợp lệ nhất) thắng; cùng độ dài thì nhánh có
+    // tổng spike_score lớn hơn thắng; hòa hẳn thì giữ nhánh đang theo.
+    async fn connect_block(&self, block: Block) -> Result<ImportOutcome, String> {
+        let parent_work = match self.storage.get_block_by_hash(&block.prev_hash) {
+            Some(parent) => {
+                if block.index != parent.index + 1 {
+                    return Err(format!("Block #{} không nối tiếp block cha #{}", block.index, parent.index));
+                }
+                self.schedule.check_block(&block, &parent)?;
+                self.storage.get_total_work(&parent.hash).unwrap_or(0.0)
+            }
+            None => {
+                self.add_orphan(block).await;
+                return Ok(ImportOutcome::Orphan);
+            }
+        };
+
+        let total_work = parent_work + block_work(block.spike_score);
+        let tip_hash = self.last_hash.read().await.clone();
+        if block.prev_hash == tip_hash {
+            // Lưu vào cây block trước: dừng giữa chừng thì block chỉ còn là nhánh phụ
+            self.storage.store_block(&block, total_work);
+            if let Err(e) = self.commit_block(&block).await {
+                self.storage.forget_block(&block.hash);
+                return Err(e);
+            }
+            return Ok(ImportOutcome::Extended);
+        }
+        self.storage.store_block(&block, total_work);
+        let tip_work = self.storage.get_total_work(&tip_hash).unwrap_or(0.0);
+        if total_work <= tip_work {
+            return Ok(ImportOutcome::SideChain);
+        }
+        let depth = self.reorganize(block).await?;
+        Ok(ImportOutcome::Reorged { depth })
+    }
+
+    // Chuyển chuỗi chính sang nhánh kết thúc ở `new_tip`; giao dịch của block bị gỡ được trả về mempool.
+    async fn reorganize(&self, new_tip: Block) -> Result<u64, String> {
+        let mut branch = vec![new_tip];
+        let (ancestor_index, ancestor_hash) = loop {
+            let last = branch.last().unwrap();
+            let parent = self.storage.get_block_by_hash(&last.prev_hash)
+                .ok_or_else(|| format!("Thiếu block cha {} khi reorg", last.prev_hash))?;
+            if self.storage.get_block(parent.index).map(|b| b.hash == parent.hash).unwrap_or(false) {
+                break (parent.index, parent.hash);
+            }
+            branch.push(parent);
+        };
+        branch.reverse();
+
+        // Không bao giờ gỡ block đã được finality chốt
+        let (finalized, _) = self.storage.get_finalized();
+        if ancestor_index < finalized {
+            return Err(format!("Nhánh mới rẽ tại #{} dưới checkpoint đã chốt #{}", ancestor_index, finalized));
+        }
+        // Block đã prune không còn bản hoàn tác để gỡ
+        let pruned = self.storage.get_pruned_height();
+        if ancestor_index < pruned {
+            return Err(format!("Nhánh mới rẽ tại #{} dưới vùng đã prune #{}", ancestor_index, pruned));
+        }
+
+        let height = *self.height.read().await;
+        let old_blocks: Vec<Block> = ((ancestor_index + 1)..=height).filter_map(|i| self.storage.get_block(i)).collect();
+        self.rewind(ancestor_index, &ancestor_hash).await;
+
+        for (i, b) in branch.iter().enumerate() {
+            if let Err(e) = self.commit_block(b).await {
+                // Nhánh mới có block sai trạng thái: quay về chuỗi cũ và loại block đó
+                self.rewind(ancestor_index, &ancestor_hash).await;
+                for bad in &branch[i..] { self.storage.forget_block(&bad.hash); }
+                for old in &old_blocks {
+                    if let Err(restore) = self.commit_block(old).await {
+                        let fault = format!("Không thể khôi phục chuỗi cũ tại block #{}: {} (nhánh mới lỗi: {})", old.index, restore, e);
+                        self.set_fault(&fault);
+                        return Err(fault);
+                    }
+                }
+                return Err(e);
+            }
+        }
+
+        let orphaned_txs = old_blocks.into_iter().flat_map(|b| b.transactions);
+        let included: HashSet<&str> = branch.iter().flat_map(|b| b.transactions.iter().map(|t| t.id.as_str())).collect();
+        for tx in orphaned_txs {
+            if !included.contains(tx.id.as_str()) { let _ = self.submit_tx(tx); }
+        }
+        println!("\n?? REORG: bỏ {} block, nối {} block mới (tổ tiên chung #{})", height - ancestor_index, branch.len(), ancestor_index);
+        Ok(height - ancestor_index)
+    }
+
+    async fn add_orphan(&self, block: Block) {
+        let mut orphans = self.orphans.lock().await;
+        if orphans.values().map(Vec::len).sum::<usize>() >= MAX_ORPHANS {
+            orphans.clear();
+        }
+        orphans.entry(block.prev_hash.clone()).or_default().push(block);
+    }
+
+    // Nối lần lượt các block mồ côi đang chờ block `hash`
+    async fn connect_orphans(&self, hash: String) {
+        let mut queue = vec![hash];
+        while let Some(parent) = queue.pop() {
+            let children = self.orphans.lock().await.remove(&parent).unwrap_or_default();
+            for child in children {
+                let child_hash = child.hash.clone();
+                match self.connect_block(child).await {
+                    Ok(ImportOutcome::Orphan) => {}
+                    Ok(_) => queue.push(child_hash),
+                    Err(e) => println!("\n?? REJECTED orphan {}: {}", child_hash, e),
+                }
+            }
+        }
+    }
+
+    /// Trả lời yêu cầu sync từ peer.
+    fn answer_sync(&self, request: SyncRequest, height: u64, tip_hash: String) -> SyncResponse {
+        match request {
+            SyncRequest::Status => SyncResponse::Status {
+                genesis_hash: self.genesis_hash.clone(),
+                height,
+                total_work: self.storage.get_total_work(&tip_hash).unwrap_or(0.0),
+                tip_hash,
+                pruned_height: self.storage.get_pruned_height(),
+            },
+            SyncRequest::GetHeaders { from_height, limit } => SyncResponse::Headers(
+                (from_height..from_height.saturating_add(limit.min(512) as u64))
+                    .map_while(|i| self.storage.get_block(i))
+                    .map(|b| b.header())
+                    .collect(),
+            ),
+            SyncRequest::GetBlock { hash } => SyncResponse::Block(self.storage.get_block_by_hash(&hash)),
         }
     }
 
+    /// Vòng lặp xử lý sự kiện từ P2P (block gossip + giao thức sync).
+    pub async fn handle_network(&self, mut rx: mpsc::UnboundedReceiver<NetEvent>) {
+        while let Some(event) = rx.recv().await {
+            match event {
+                NetEvent::Block { data, from, message_id } => {
+                    let block = match bincode::deserialize::<Block>(&data) {
+                        Ok(b) => b,
+                        Err(_) => {
+                            println!("\n?? P2P: Block lỗi định dạng từ {}", from);
+                            self.p2p.report(message_id, from, MessageAcceptance::Reject);
+                            continue;
+                        }
+                    };
+                    let index = block.index;
+                    if let Err(e) = block.verify_seal() {
+                        println!("\n?? REJECTED Block #{} từ {}: {}", index, from, e);
+                        self.p2p.report(message_id, from, MessageAcceptance::Reject);
+                        continue;
+                    }
+                    let result = self.import_block(block).await;
+                    let acceptance = if result.is_ok() { MessageAcceptance::Accept } else { MessageAcceptance::Ignore };
+                    self.p2p.report(message_id, from, acceptance);
+                    match result {
+                        Ok(ImportOutcome::Reorged { depth }) => println!("\n?? IMPORTED Block #{} từ {} (reorg sâu {})", index, from, depth),
+                        // Thiếu block cha => mình đang tụt lại, hỏi trạng thái peer để tải bù
+                        Ok(ImportOutcome::Orphan) => self.sync.probe(self, from).await,
+                        Ok(outcome) => println!("\n?? IMPORTED Block #{} từ {}: {:?}", index, from, outcome),
+                        Err(e) => println!("\n?? IGNORED Block #{} từ {}: {}", index, from, e),
+                    }
+                }
+                NetEvent::Transaction { data, from, message_id } => {
+                    // Chỉ chuyển tiếp giao dịch đã qua kiểm tra mempool; giao dịch sai hẳn thì phạt peer relay
+                    let acceptance = match bincode::deserialize::<Transaction>(&data) {
+                        Ok(tx) => match self.submit_tx(tx) {
+                            Ok(()) => MessageAcceptance::Accept,
+                            Err(reject) if reject.is_invalid() => MessageAcceptance::Reject,
+                            Err(_) => MessageAcceptance::Ignore,
+                        },
+                        Err(_) => MessageAcceptance::Reject,
+                    };
+                    self.p2p.report(message_id, from, acceptance);
+                }
+                NetEvent::Vote { data, from, message_id } => {
+                    let acceptance = match bincode::deserialize::<Vote>(&data) {
+                        Ok(vote) => match self.finality.on_vote(self, vote).await {
+                            VoteOutcome::Accepted => MessageAcceptance::Accept,
+                            VoteOutcome::Ignored => MessageAcceptance::Ignore,
+                            VoteOutcome::Invalid(e) => {
+                                println!("\n?? REJECTED vote từ {}: {}", from, e);
+                                MessageAcceptance::Reject
+                            }
+                        },
+                        Err(_) => MessageAcceptance::Reject,
+                    };
+                    self.p2p.report(message_id, from, acceptance);
+                }
+                NetEvent::PeerConnected(peer) => self.sync.probe(self, peer).await,
+                NetEvent::SyncRequest { request, channel, .. } => {
+                    let height = *self.height.read().await;
+                    let tip_hash = self.last_hash.read().await.clone();
+                    self.p2p.respond_sync(channel, self.answer_sync(request, height, tip_hash));
+                }
+                NetEvent::SyncResponse { peer, response } => self.sync.on_response(self, peer, response).await,
+                NetEvent::SyncFailed { peer } => self.sync.on_failure(peer).await,
+            }
+        }
+    }
+
+    /// Vòng lặp đề xuất block: mỗi slot kiểm tra lịch PoA, chỉ đề xuất khi slot thuộc về node này.
     pub async fn run(&self) {
-        println!("??  MINING STARTED (800ms/block)");
         let pub_key_hex = hex::encode(&self.wallet.public_key);
-        
+        if self.schedule.is_open() {
+            println!("??  PoA: Chain spec chưa khai báo validator, chạy chế độ mở ({}ms/slot)", self.schedule.interval_ms());
+        } else if self.schedule.is_validator(&pub_key_hex) {
+            println!("??  PoA: Node là validator, đề xuất block theo lịch ({}ms/slot)", self.schedule.interval_ms());
+        } else {
+            println!("??  PoA: Node không nằm trong validator set, chỉ theo dõi chuỗi");
+        }
+
         loop {
-            let start = Instant::now();
-
-            // 1. Mining
-            let spike = self.snn.forward(1.0).await;
-
-            // 2. Update Height
-            let mut h_guard = self.height.write().await;
-            *h_guard += 1;
-            let current_height = *h_guard;
-            drop(h_guard);
-
-            let prev_hash = self.last_hash.read().await.clone();
-
-            // 3. L?y giao d?ch
-            let txs = self.mempool.pop_n(50);
-            let tx_count = txs.len();
-
-            // 4. T?o Block
-            let mut block = Block::new(
-                current_height,
-                prev_hash,
-                txs,
-                spike,
-                "choithuti_NODE".to_string(),
-                pub_key_hex.clone(),
-            );
-
-            // 5. Ký & Luu
-            let signature = self.wallet.sign_data(block.hash.as_bytes()).await;
-            block.pqc_signature = hex::encode(signature);
-            
-            *self.last_hash.write().await = block.hash.clone();
-            self.storage.save_block(&block);
-            
-            let mut history = self.blocks_history.write().await;
-            if history.len() >= 15 { history.pop_front(); }
-            history.push_back(block.clone());
-            drop(history);
-
-            // 6. Broadcast P2P
-            if let Ok(data) = bincode::serialize(&block) {
-                let mut p2p = self.p2p_node.lock().await;
-                p2p.broadcast_block(data);
+            tokio::time::sleep(std::time::Duration::from_millis(self.schedule.millis_to_next_slot())).await;
+            let slot = self.schedule.current_slot();
+
+            if self.fault().is_some() {
+                println!("\n❌ PoA: Node đang lỗi, dừng đề xuất block");
+                return;
+            }
+            // 0. Chưa bắt kịp mạng hoặc không tới lượt thì không đề xuất
+            if !self.sync.is_synced().await || !self.schedule.is_my_slot(slot, &pub_key_hex) {
+                continue;
             }
 
-            let elapsed = start.elapsed();
-            print!("\r\x1b[K?? Block #{} | Tx: {} | Spike: {:.3}", block.index, tx_count, block.spike_score);
-            io::stdout().flush().unwrap();
+            match self.propose(slot).await {
+                Ok(Some((block, tx_count))) => {
+                    print!("\r\x1b[K?? Block #{} | Slot: {} | Tx: {} | Spike: {:.3}", block.index, slot, tx_count, block.spike_score);
+                    io::stdout().flush().unwrap();
+                }
+                Ok(None) => {}
+                Err(e) => println!("\n?? {}", e),
+            }
+        }
+    }
+
+    /// Dựng, ký, nối và phát block cho `slot` trên đỉnh hiện tại (lịch PoA và trạng thái sync do người gọi kiểm tra).
+    /// `None` nếu đỉnh đã ở slot này hoặc mới hơn; trả về kèm số giao dịch thường trong block.
+    pub async fn propose(&self, slot: u64) -> Result<Option<(Block, usize)>, String> {
+        let pub_key_hex = hex::encode(&self.wallet.public_key);
+        // 1. Update Height
+        let tip = self.tip_lock.lock().await;
+        let prev_hash = self.last_hash.read().await.clone();
+        match self.storage.get_block_by_hash(&prev_hash) {
+            Some(parent) if parent.slot < slot => {}
+            _ => return Ok(None),
+        }
+        let current_height = *self.height.read().await + 1;
+
+        // 2. Mining: proof-of-neural-work trên block cha, peer sẽ chạy lại để kiểm tra
+        let spike = neural_work(&prev_hash, &pub_key_hex, slot);
+
+        // 3. L?y giao d?ch (bỏ giao dịch sai chữ ký để peer không từ chối block)
+        let mut view = StateView::new(&*self.storage, current_height, &self.spec.governance);
+        let params = ChainParams::load(&view).map_err(|e| format!("Không đọc được tham số chuỗi: {}", e))?;
+        let reward = params.block_reward(&self.spec, current_height);
+        let mut txs = self.mempool.select(params.max_block_txs as usize);
+        webnode::anchor_epoch(&mut view, &prev_hash);
+        txs.retain(|tx| tx.id == tx.calculate_hash() && tx.verify() && view.apply_tx(tx).is_ok());
+        let tx_count = txs.len();
+        let (coinbases, released) = self.build_coinbases(&mut view, reward, txs.iter().map(|tx| tx.fee).sum());
+        coinbases.iter().try_for_each(|cb| view.apply_coinbase(cb)).map_err(|e| format!("Không thể tạo coinbase: {}", e))?;
+        txs.splice(0..0, coinbases);
+        NeuroDAO::close_ended(&mut view);
+        NeuroDAO::activate_due(&mut view, slot)
+            .and_then(|_| view.release_reward(&self.spec.emission.reward_pool, released))
+            .and_then(|_| params::add_emitted(&mut view, released))
+            .map_err(|e| format!("Không thể trả thưởng: {}", e))?;
+        let state_root = view.root();
 
-            if elapsed.as_millis() < 800 {
-                tokio::time::sleep(std::time::Duration::from_millis(800) - elapsed).await;
+        // 4. T?o Block
+        let miner = self.schedule.proposer(slot).map(|v| v.name.clone()).unwrap_or_else(|| self.identity.name.clone());
+        let mut block = Block::new(
+            current_height,
+            slot,
+            prev_hash,
+            state_root,
+            txs,
+            spike,
+            miner,
+            pub_key_hex,
+        );
+
+        // 5. Ký & Luu
+        let signature = self.wallet.sign_data(block.hash.as_bytes()).await;
+        block.pqc_signature = hex::encode(signature);
+
+        self.connect_block(block.clone()).await.map_err(|e| format!("Không thể nối block tự đào: {}", e))?;
+        drop(tip);
+
+        // 6. Broadcast P2P
+        if let Ok(data) = bincode::serialize(&block) {
+            self.p2p.broadcast_block(data);
+        }
+        self.finality.on_new_tip(self).await;
+        Ok(Some((block, tx_count)))
+    }
+}
+
+// Mạng thử nhiều node trong cùng process: mỗi node một MemoryStore và một swarm libp2p thật trên 127.0.0.1
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::{EmissionSpec, ValidatorSpec, PAPPAP_TOTAL_SUPPLY};
+    use crate::governance::GovernanceParams;
+    use crate::keystore::NodeKeys;
+    use crate::memstore::MemoryStore;
+    use crate::p2p::P2PNode;
+    use crate::wallet::Wallet;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::{Duration, Instant};
+
+    const TIMEOUT: Duration = Duration::from_secs(120);
+
+    fn test_spec(validators: &[(&str, &NodeKeys)], balances: &[(&str, u64)], interval_ms: u64) -> ChainSpec {
+        let mut all: BTreeMap<String, u64> = balances.iter().map(|(a, b)| (a.to_string(), *b)).collect();
+        all.insert("COMMUNITY_POOL".to_string(), 1_000_000_000_000);
+        ChainSpec {
+            chain_id: "pappap-test".to_string(),
+            genesis_timestamp: chrono::Utc::now().timestamp() - 1,
+            block_interval_ms: interval_ms,
+            validators: validators.iter()
+                .map(|(name, keys)| ValidatorSpec { name: name.to_string(), pqc_pubkey: hex::encode(&keys.pqc_public), stake: 1 })
+                .collect(),
+            balances: all,
+            emission: EmissionSpec { total_supply: PAPPAP_TOTAL_SUPPLY, reward_pool: "COMMUNITY_POOL".to_string(), initial_reward: 950_000_000, halving_interval: 0 },
+            governance: GovernanceParams::default(),
+        }
+    }
+
+    struct TestNode {
+        chain: Arc<PappapChain>,
+        addr: String,
+    }
+
+    async fn start_node(spec: &ChainSpec, keys: NodeKeys, name: &str) -> TestNode {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let addr = format!("/ip4/127.0.0.1/tcp/{}", port);
+        let (mut p2p, net_rx, _) = P2PNode::new(keys.libp2p_keypair(), Arc::new(AtomicUsize::new(0)), &addr).await.unwrap();
+        let wallet = keys.wallet();
+        let identity = NodeIdentity::bind(&keys, &wallet, name).await;
+        let storage: Arc<dyn ChainStore> = Arc::new(MemoryStore::new());
+        let chain = Arc::new(PappapChain::new(storage, SmartCache::new(), p2p.handle(), spec.clone(), wallet, identity).await);
+        tokio::spawn(async move { p2p.run().await; });
+        let net = chain.clone();
+        tokio::spawn(async move { net.handle_network(net_rx).await; });
+        TestNode { chain, addr }
+    }
+
+    // Đề xuất `count` block ở các slot hiện tại (bỏ qua lịch chờ của `run`)
+    async fn produce(node: &TestNode, count: usize) {
+        for _ in 0..count {
+            loop {
+                let slot = node.chain.schedule.current_slot();
+                if node.chain.schedule.is_my_slot(slot, &node.chain.identity.pqc_pubkey) && node.chain.propose(slot).await.unwrap().is_some() { break; }
+                tokio::time::sleep(Duration::from_millis(20)).await;
             }
         }
     }
+
+    async fn wait_for(what: &str, check: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !check() {
+            assert!(start.elapsed() < TIMEOUT, "Hết thời gian chờ: {}", what);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn observer_syncs_history_then_follows_gossip() {
+        let keys = NodeKeys::generate(None);
+        let spec = test_spec(&[("A", &keys)], &[], 100);
+        let a = start_node(&spec, keys, "A").await;
+        produce(&a, 4).await;
+
+        // B không phải validator: tải lịch sử qua giao thức sync rồi nhận block mới qua gossip
+        let b = start_node(&spec, NodeKeys::generate(None), "B").await;
+        b.chain.p2p.dial(&a.addr);
+        wait_for("B tải xong 4 block", || b.chain.storage.get_height() >= 4).await;
+        assert_eq!(b.chain.storage.get_block(4).unwrap().hash, a.chain.storage.get_block(4).unwrap().hash);
+
+        produce(&a, 2).await;
+        wait_for("B theo kịp A", || b.chain.storage.get_last_hash() == a.chain.storage.get_last_hash()).await;
+        assert_eq!(b.chain.storage.get_height(), 6);
+        assert_eq!(b.chain.storage.state_root(), a.chain.storage.state_root());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shorter_fork_reorgs_and_requeues_orphaned_tx() {
+        let sender = Wallet::new();
+        let spec = test_spec(&[], &[(&sender.address, 1_000_000_000)], 100);
+        let a = start_node(&spec, NodeKeys::generate(None), "A").await;
+        let b = start_node(&spec, NodeKeys::generate(None), "B").await;
+
+        // Hai node chưa nối mạng đào hai nhánh riêng; giao dịch chỉ nằm trên nhánh ngắn của A
+        let tx = Transaction::signed(&sender, 0, 10, TxPayload::Transfer { receiver: "PAPPAP00000000000000000000000000000001".to_string(), amount: 5 });
+        a.chain.submit_tx(tx.clone()).unwrap();
+        produce(&a, 2).await;
+        assert!(a.chain.storage.get_tx_location(&tx.id).is_some());
+        produce(&b, 4).await;
+
+        a.chain.p2p.dial(&b.addr);
+        wait_for("A chuyển sang nhánh dài hơn của B", || a.chain.storage.get_last_hash() == b.chain.storage.get_last_hash()).await;
+        assert_eq!(a.chain.storage.get_height(), 4);
+        assert_eq!(a.chain.storage.get_block(1).unwrap().hash, b.chain.storage.get_block(1).unwrap().hash);
+        assert_eq!(a.chain.storage.state_root(), b.chain.storage.state_root());
+        assert!(a.chain.storage.get_tx_location(&tx.id).is_none());
+        assert!(a.chain.mempool.get(&tx.id).is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn three_validators_finalize_same_blocks() {
+        let keys: Vec<NodeKeys> = (0..3).map(|_| NodeKeys::generate(None)).collect();
+        let names = ["A", "B", "C"];
+        let validators: Vec<(&str, &NodeKeys)> = names.iter().copied().zip(keys.iter()).collect();
+        let spec = test_spec(&validators, &[], 1_000);
+        let mut nodes = Vec::new();
+        for (name, k) in names.iter().zip(keys) { nodes.push(start_node(&spec, k, name).await); }
+        nodes[0].chain.p2p.dial(&nodes[1].addr);
+        nodes[0].chain.p2p.dial(&nodes[2].addr);
+        nodes[1].chain.p2p.dial(&nodes[2].addr);
+        for node in &nodes {
+            let chain = node.chain.clone();
+            tokio::spawn(async move { chain.run().await; });
+        }
+
+        // Cần phiếu của cả 3 validator (> 2/3 trọng số) để chốt
+        wait_for("cả 3 node chốt tới height 3", || nodes.iter().all(|n| n.chain.storage.get_finalized().0 >= 3)).await;
+        let finalized = nodes.iter().map(|n| n.chain.storage.get_finalized().0).min().unwrap();
+        let hashes: HashSet<String> = nodes.iter().map(|n| n.chain.storage.get_block(finalized).unwrap().hash).collect();
+        assert_eq!(hashes.len(), 1);
+        for node in &nodes {
+            let qc = node.chain.storage.get_qc(finalized).unwrap();
+            assert_eq!(qc.block_hash, *hashes.iter().next().unwrap());
+            assert!(qc.verify(&spec).is_ok());
+        }
+    }
 }
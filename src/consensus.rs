@@ -0,0 +1,90 @@
+use crate::block::Block;
+use crate::genesis::{ChainSpec, ValidatorSpec};
+use crate::params::{ChainParams, SlotTiming};
+use crate::snn_core::{CANONICAL_NEURONS, WORK_STEPS};
+use chrono::Utc;
+use std::sync::{Arc, RwLock};
+
+// Cho phép đồng hồ peer chạy nhanh hơn tối đa 1 slot
+const MAX_FUTURE_SLOTS: u64 = 1;
+
+/// Công của một block dùng cho fork choice: 1 cho mỗi block cộng phần lẻ spike_score / mức tối đa,
+/// nên độ dài chuỗi luôn quyết định trước, spike_score chỉ phân định các nhánh dài bằng nhau.
+pub fn block_work(spike_score: f32) -> f64 {
+    1.0 + spike_score as f64 / (CANONICAL_NEURONS * WORK_STEPS + 1) as f64
+}
+
+/// Lịch đề xuất block PoA: thời gian chia thành slot theo `SlotTiming` trong tham số chuỗi
+/// (block interval đổi được qua governance), slot `s` thuộc về validator `s % n` trong danh sách của chain spec.
+#[derive(Clone)]
+pub struct Schedule {
+    validators: Vec<ValidatorSpec>,
+    timing: Arc<RwLock<SlotTiming>>,
+}
+
+impl Schedule {
+    pub fn from_spec(spec: &ChainSpec) -> Self {
+        Self {
+            validators: spec.validators.clone(),
+            timing: Arc::new(RwLock::new(ChainParams::initial(spec).timing)),
+        }
+    }
+
+    /// Cập nhật cách chia slot theo tham số của đỉnh chuỗi chính.
+    pub fn set_timing(&self, timing: SlotTiming) {
+        *self.timing.write().unwrap() = timing;
+    }
+
+    pub fn interval_ms(&self) -> u64 {
+        self.timing.read().unwrap().interval_ms
+    }
+
+    /// Không khai báo validator => chế độ mở (dev/testnet): node nào cũng được đề xuất.
+    pub fn is_open(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    pub fn current_slot(&self) -> u64 {
+        self.timing.read().unwrap().slot_at(Utc::now().timestamp_millis())
+    }
+
+    /// Số mili giây còn lại tới đầu slot kế tiếp.
+    pub fn millis_to_next_slot(&self) -> u64 {
+        let timing = self.timing.read().unwrap();
+        let now = Utc::now().timestamp_millis();
+        (timing.slot_start_ms(timing.slot_at(now) + 1) - now).max(1) as u64
+    }
+
+    pub fn proposer(&self, slot: u64) -> Option<&ValidatorSpec> {
+        if self.is_open() { return None; }
+        self.validators.get((slot % self.validators.len() as u64) as usize)
+    }
+
+    pub fn is_my_slot(&self, slot: u64, pqc_pubkey: &str) -> bool {
+        match self.proposer(slot) {
+            Some(v) => v.pqc_pubkey == pqc_pubkey,
+            None => self.is_open(),
+        }
+    }
+
+    pub fn is_validator(&self, pqc_pubkey: &str) -> bool {
+        self.validators.iter().any(|v| v.pqc_pubkey == pqc_pubkey)
+    }
+
+    /// Kiểm tra block (chữ ký đã được `verify_seal` xác nhận khớp `miner_pqc_pubkey`):
+    /// slot tăng dần so với block cha, không ở tương lai, và đúng validator được xếp lịch.
+    pub fn check_block(&self, block: &Block, parent: &Block) -> Result<(), String> {
+        if block.slot <= parent.slot {
+            return Err(format!("Block #{} có slot {} không lớn hơn slot cha {}", block.index, block.slot, parent.slot));
+        }
+        if block.slot > self.current_slot() + MAX_FUTURE_SLOTS {
+            return Err(format!("Block #{} thuộc slot tương lai {}", block.index, block.slot));
+        }
+        if let Some(v) = self.proposer(block.slot) {
+            if v.pqc_pubkey != block.miner_pqc_pubkey || v.name != block.miner {
+                return Err(format!("Block #{}: slot {} thuộc về validator '{}', không phải '{}'", block.index, block.slot, v.name, block.miner));
+            }
+        }
+        Ok(())
+    }
+}
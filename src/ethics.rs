@@ -10,9 +10,14 @@ impl EthicsFilter {
     ];
 
     pub fn check(content: &str) -> Result<(), String> {
+        Self::check_with(content, &[])
+    }
+
+    /// Như `check`, cộng thêm các từ khóa cấm được thêm qua governance (`ChainParams::ethics_blacklist`).
+    pub fn check_with(content: &str, extra: &[String]) -> Result<(), String> {
         let lower_content = content.to_lowercase();
         
-        for &word in Self::BLACKLIST.iter() {
+        for word in Self::BLACKLIST.iter().copied().chain(extra.iter().map(String::as_str)) {
             if lower_content.contains(word) {
                 return Err(format!(
                     "⚠️ NỘI DUNG BỊ TỪ CHỐI: Vi phạm quy tắc an toàn thông tin & Luật An ninh mạng (Phát hiện từ khóa: '{}').", 
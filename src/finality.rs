@@ -0,0 +1,202 @@
+use crate::chain::PappapChain;
+use crate::genesis::ChainSpec;
+use crate::quantum::QuantumWallet;
+use serde::{Serialize, Deserialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VoteKind {
+    Prevote,
+    Precommit,
+}
+
+/// Phiếu của validator cho một block hash ở một height, ký bằng khóa Dilithium (QuantumWallet).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Vote {
+    pub kind: VoteKind,
+    pub height: u64,
+    pub block_hash: String,
+    pub validator: String,
+    pub signature: String,
+}
+
+impl Vote {
+    fn payload(kind: VoteKind, height: u64, block_hash: &str, chain_id: &str) -> Vec<u8> {
+        format!("{}:{:?}:{}:{}", chain_id, kind, height, block_hash).into_bytes()
+    }
+
+    pub async fn sign(wallet: &QuantumWallet, kind: VoteKind, height: u64, block_hash: &str, chain_id: &str) -> Self {
+        let signature = wallet.sign_data(&Self::payload(kind, height, block_hash, chain_id)).await;
+        Self {
+            kind,
+            height,
+            block_hash: block_hash.to_string(),
+            validator: hex::encode(&wallet.public_key),
+            signature: hex::encode(signature),
+        }
+    }
+
+    pub fn verify(&self, chain_id: &str) -> bool {
+        match (hex::decode(&self.validator), hex::decode(&self.signature)) {
+            (Ok(pk), Ok(sig)) => QuantumWallet::verify_data(&Self::payload(self.kind, self.height, &self.block_hash, chain_id), &sig, &pk),
+            _ => false,
+        }
+    }
+}
+
+/// Chứng chỉ quorum: các precommit chiếm > 2/3 tổng trọng số validator cho cùng (height, hash).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QuorumCertificate {
+    pub height: u64,
+    pub block_hash: String,
+    pub votes: Vec<Vote>,
+}
+
+impl QuorumCertificate {
+    /// Kiểm tra QC chỉ dựa vào chain spec (vd. khi nạp snapshot): precommit đúng chữ ký cho cùng (height, hash)
+    /// của các validator khác nhau, tổng trọng số > 2/3.
+    pub fn verify(&self, spec: &ChainSpec) -> Result<(), String> {
+        let total: u64 = spec.validators.iter().map(|v| v.stake.max(1)).sum();
+        let mut voters = HashSet::new();
+        for vote in &self.votes {
+            if vote.kind != VoteKind::Precommit || vote.height != self.height || vote.block_hash != self.block_hash {
+                return Err("QC chứa phiếu không khớp".to_string());
+            }
+            if !vote.verify(&spec.chain_id) {
+                return Err(format!("QC có phiếu sai chữ ký của {}...", &vote.validator[..16.min(vote.validator.len())]));
+            }
+            voters.insert(vote.validator.as_str());
+        }
+        let voted: u64 = spec.validators.iter().filter(|v| voters.contains(v.pqc_pubkey.as_str())).map(|v| v.stake.max(1)).sum();
+        if total == 0 || voted * 3 <= total * 2 {
+            return Err(format!("QC không đủ quorum ({} / {})", voted, total));
+        }
+        Ok(())
+    }
+}
+
+/// Kết quả nhận phiếu từ mạng, dùng để báo cho gossipsub chuyển tiếp hay phạt peer.
+pub enum VoteOutcome {
+    Accepted,
+    Ignored,
+    Invalid(String),
+}
+
+type VoteKey = (VoteKind, u64, String);
+
+#[derive(Default)]
+struct FinalityInner {
+    votes: HashMap<VoteKey, HashMap<String, Vote>>,
+    // QC đã đủ nhưng block chưa nằm trên chuỗi chính của node này
+    pending: Vec<QuorumCertificate>,
+}
+
+/// Lớp finality kiểu Tendermint rút gọn: prevote -> precommit -> finalized, không có vòng (round).
+pub struct FinalityGadget {
+    inner: Mutex<FinalityInner>,
+}
+
+impl FinalityGadget {
+    pub fn new() -> Self {
+        Self { inner: Mutex::new(FinalityInner::default()) }
+    }
+
+    fn weight_of(chain: &PappapChain, validator: &str) -> Option<u64> {
+        chain.spec.validators.iter().find(|v| v.pqc_pubkey == validator).map(|v| v.stake.max(1))
+    }
+
+    fn has_quorum(chain: &PappapChain, votes: &HashMap<String, Vote>) -> bool {
+        let total: u64 = chain.spec.validators.iter().map(|v| v.stake.max(1)).sum();
+        let voted: u64 = votes.keys().filter_map(|v| Self::weight_of(chain, v)).sum();
+        total > 0 && voted * 3 > total * 2
+    }
+
+    // Giành quyền ký phiếu `kind` ở `height`: height phải tăng dần theo từng loại phiếu, và phiếu được ghi bền
+    // vào kho trước khi phát nên khởi động lại cũng không ký hai block khác nhau ở cùng height
+    async fn claim(&self, chain: &PappapChain, kind: VoteKind, height: u64, block_hash: &str) -> bool {
+        let _guard = self.inner.lock().await;
+        if chain.storage.get_last_signed(kind).map(|(h, _)| h >= height).unwrap_or(false) { return false; }
+        chain.storage.set_last_signed(kind, height, block_hash);
+        true
+    }
+
+    /// Gọi sau khi đỉnh chuỗi thay đổi: validator prevote cho block đỉnh, và thử áp các QC đang chờ.
+    pub async fn on_new_tip(&self, chain: &PappapChain) {
+        let height = *chain.height.read().await;
+        let tip_hash = chain.last_hash.read().await.clone();
+        let my_key = hex::encode(&chain.wallet.public_key);
+        let (finalized, _) = chain.storage.get_finalized();
+
+        let pending: Vec<QuorumCertificate> = std::mem::take(&mut self.inner.lock().await.pending);
+        for qc in pending { self.try_finalize(chain, qc).await; }
+
+        if height <= finalized || Self::weight_of(chain, &my_key).is_none() || !chain.sync.is_synced().await { return; }
+        if !self.claim(chain, VoteKind::Prevote, height, &tip_hash).await { return; }
+        let vote = Vote::sign(&chain.wallet, VoteKind::Prevote, height, &tip_hash, &chain.spec.chain_id).await;
+        chain.p2p.broadcast_vote(bincode::serialize(&vote).unwrap());
+        self.add_vote(chain, vote).await;
+    }
+
+    /// Nhận phiếu từ peer.
+    pub async fn on_vote(&self, chain: &PappapChain, vote: Vote) -> VoteOutcome {
+        if Self::weight_of(chain, &vote.validator).is_none() {
+            return VoteOutcome::Invalid("Phiếu từ node không phải validator".to_string());
+        }
+        if !vote.verify(&chain.spec.chain_id) {
+            return VoteOutcome::Invalid("Sai chữ ký phiếu".to_string());
+        }
+        if vote.height <= chain.storage.get_finalized().0 {
+            return VoteOutcome::Ignored;
+        }
+        if self.add_vote(chain, vote).await { VoteOutcome::Accepted } else { VoteOutcome::Ignored }
+    }
+
+    // Ghi phiếu (đã kiểm tra) và xử lý khi đạt quorum. Trả về false nếu phiếu trùng.
+    async fn add_vote(&self, chain: &PappapChain, vote: Vote) -> bool {
+        let key = (vote.kind, vote.height, vote.block_hash.clone());
+        let (kind, height, block_hash) = key.clone();
+        let quorum_votes = {
+            let mut inner = self.inner.lock().await;
+            let entry = inner.votes.entry(key).or_default();
+            if entry.contains_key(&vote.validator) { return false; }
+            entry.insert(vote.validator.clone(), vote);
+            if Self::has_quorum(chain, entry) { Some(entry.values().cloned().collect::<Vec<_>>()) } else { None }
+        };
+        let votes = match quorum_votes { Some(v) => v, None => return true };
+
+        match kind {
+            VoteKind::Prevote => {
+                let my_key = hex::encode(&chain.wallet.public_key);
+                if Self::weight_of(chain, &my_key).is_some()
+                    && chain.storage.get_block_by_hash(&block_hash).is_some()
+                    && self.claim(chain, VoteKind::Precommit, height, &block_hash).await
+                {
+                    let precommit = Vote::sign(&chain.wallet, VoteKind::Precommit, height, &block_hash, &chain.spec.chain_id).await;
+                    chain.p2p.broadcast_vote(bincode::serialize(&precommit).unwrap());
+                    Box::pin(self.add_vote(chain, precommit)).await;
+                }
+            }
+            VoteKind::Precommit => {
+                self.try_finalize(chain, QuorumCertificate { height, block_hash, votes }).await;
+            }
+        }
+        true
+    }
+
+    // Chốt height nếu block của QC đang nằm trên chuỗi chính; nếu chưa thì giữ lại chờ.
+    async fn try_finalize(&self, chain: &PappapChain, qc: QuorumCertificate) {
+        if qc.height <= chain.storage.get_finalized().0 { return; }
+        let canonical = chain.storage.get_block(qc.height).map(|b| b.hash == qc.block_hash).unwrap_or(false);
+        if !canonical {
+            self.inner.lock().await.pending.push(qc);
+            return;
+        }
+        chain.storage.set_finalized(&qc);
+        println!("\n?? FINALIZED Block #{} ({} phiếu precommit)", qc.height, qc.votes.len());
+
+        let mut inner = self.inner.lock().await;
+        inner.votes.retain(|(_, h, _), _| *h > qc.height);
+        inner.pending.retain(|p| p.height > qc.height);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::block::Block;
+use crate::state::{Account, compute_root};
+use crate::merkle::merkle_root;
+use crate::governance::GovernanceParams;
+use crate::params::{ChainParams, PARAMS_KEY};
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ValidatorSpec {
+    pub name: String,
+    pub pqc_pubkey: String,
+    #[serde(default)]
+    pub stake: u64,
+}
+
+/// Tổng cung tối đa: 1 tỷ PAPPAP với 9 chữ số thập phân của ledger. Cùng số token với `core::token::PappapToken`
+/// (module đó không được biên dịch và tính theo 18 chữ số thập phân nên không so trực tiếp được).
+pub const PAPPAP_TOTAL_SUPPLY: u64 = 1_000_000_000_000_000_000;
+
+fn default_reward_pool() -> String {
+    "COMMUNITY_POOL".to_string()
+}
+
+/// Lịch trả phần thưởng block: `initial_reward` giảm một nửa sau mỗi `halving_interval` block (0 = không halving).
+/// Phần thưởng không đúc thêm mà rút từ tài khoản `reward_pool` được phân bổ ở genesis, hết quỹ thì thôi trả;
+/// tổng cung cố định bằng phân bổ genesis và không vượt `total_supply`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EmissionSpec {
+    pub total_supply: u64,
+    #[serde(default = "default_reward_pool")]
+    pub reward_pool: String,
+    pub initial_reward: u64,
+    pub halving_interval: u64,
+}
+
+impl Default for EmissionSpec {
+    fn default() -> Self {
+        Self { total_supply: PAPPAP_TOTAL_SUPPLY, reward_pool: default_reward_pool(), initial_reward: 0, halving_interval: 0 }
+    }
+}
+
+/// Thông số chuỗi đọc từ `chainspec.toml`; block #0 được dựng tất định từ đây.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChainSpec {
+    pub chain_id: String,
+    pub genesis_timestamp: i64,
+    pub block_interval_ms: u64,
+    #[serde(default)]
+    pub validators: Vec<ValidatorSpec>,
+    #[serde(default)]
+    pub balances: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub emission: EmissionSpec,
+    #[serde(default)]
+    pub governance: GovernanceParams,
+}
+
+impl ChainSpec {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let raw = std::fs::read_to_string(path).map_err(|e| format!("Không đọc được chain spec '{}': {}", path, e))?;
+        let spec: Self = toml::from_str(&raw).map_err(|e| format!("Chain spec '{}' sai định dạng: {}", path, e))?;
+        if spec.genesis_supply() > spec.emission.total_supply as u128 {
+            return Err(format!("Chain spec '{}': phân bổ genesis vượt total_supply", path));
+        }
+        if spec.emission.initial_reward > 0 && !spec.balances.contains_key(&spec.emission.reward_pool) {
+            return Err(format!("Chain spec '{}': quỹ thưởng '{}' không có trong [balances]", path, spec.emission.reward_pool));
+        }
+        Ok(spec)
+    }
+
+    pub fn genesis_supply(&self) -> u128 {
+        self.balances.values().map(|b| *b as u128).sum()
+    }
+
+    // Tổng phần thưởng các block 1..=height theo lịch halving, chưa tính giới hạn quỹ
+    fn scheduled_emission(&self, height: u64) -> u128 {
+        let e = &self.emission;
+        let (mut total, mut start, mut era) = (0u128, 1u64, 0u32);
+        while start <= height && era < 64 {
+            let reward = (e.initial_reward >> era) as u128;
+            if reward == 0 { break; }
+            let end = if e.halving_interval == 0 { height } else { height.min(start.saturating_add(e.halving_interval - 1)) };
+            total = total.saturating_add(reward * (end - start + 1) as u128);
+            if end == height { break; }
+            start = end + 1;
+            era += 1;
+        }
+        total
+    }
+
+    /// Tổng phần thưởng được rút khỏi quỹ tới hết block `height`: lịch halving, tối đa bằng phân bổ genesis của quỹ.
+    pub fn released_at(&self, height: u64) -> u128 {
+        let pool = self.balances.get(&self.emission.reward_pool).copied().unwrap_or(0) as u128;
+        self.scheduled_emission(height).min(pool)
+    }
+
+    /// Phần thưởng theo lịch phát hành của block `height` (không gồm phí); phần thực trả còn nhân
+    /// `ChainParams::reward_percent`, xem `ChainParams::block_reward`.
+    pub fn block_reward(&self, height: u64) -> u64 {
+        if height == 0 { return 0; }
+        (self.released_at(height) - self.released_at(height - 1)) as u64
+    }
+
+    /// Hash của toàn bộ spec (dùng làm prev_hash của block #0).
+    pub fn spec_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(bincode::serialize(self).unwrap());
+        hex::encode(hasher.finalize())
+    }
+
+    pub fn genesis_accounts(&self) -> BTreeMap<String, Account> {
+        self.balances.iter()
+            .map(|(addr, balance)| (addr.clone(), Account { balance: *balance, ..Default::default() }))
+            .collect()
+    }
+
+    /// Dữ liệu module trong state genesis: tham số chuỗi ban đầu.
+    pub fn genesis_data(&self) -> BTreeMap<String, Vec<u8>> {
+        BTreeMap::from([(PARAMS_KEY.to_string(), ChainParams::initial(self).encode())])
+    }
+
+    pub fn genesis_block(&self) -> Block {
+        let mut block = Block {
+            index: 0,
+            slot: 0,
+            timestamp: self.genesis_timestamp,
+            prev_hash: self.spec_hash(),
+            hash: String::new(),
+            state_root: compute_root(&self.genesis_accounts(), &self.genesis_data()),
+            tx_root: merkle_root(&[]),
+            transactions: Vec::new(),
+            spike_score: 0.0,
+            miner: "genesis".to_string(),
+            miner_pqc_pubkey: String::new(),
+            pqc_signature: String::new(),
+        };
+        block.hash = block.calculate_hash();
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(initial_reward: u64, halving_interval: u64, pool: u64) -> ChainSpec {
+        ChainSpec {
+            chain_id: "pappap-test".to_string(),
+            genesis_timestamp: 0,
+            block_interval_ms: 800,
+            validators: Vec::new(),
+            balances: BTreeMap::from([("COMMUNITY_POOL".to_string(), pool)]),
+            emission: EmissionSpec { initial_reward, halving_interval, ..Default::default() },
+            governance: GovernanceParams::default(),
+        }
+    }
+
+    #[test]
+    fn reward_halves_exactly_at_interval_boundary() {
+        let spec = spec(100, 4, u64::MAX);
+        assert_eq!(spec.block_reward(0), 0);
+        assert_eq!(spec.block_reward(1), 100);
+        assert_eq!(spec.block_reward(4), 100);
+        assert_eq!(spec.block_reward(5), 50);
+        assert_eq!(spec.block_reward(8), 50);
+        assert_eq!(spec.block_reward(9), 25);
+        assert_eq!(spec.released_at(8), 600);
+    }
+
+    #[test]
+    fn reward_stops_when_halving_reaches_zero() {
+        let spec = spec(3, 2, u64::MAX);
+        let rewards: Vec<u64> = (1..=7).map(|h| spec.block_reward(h)).collect();
+        assert_eq!(rewards, vec![3, 3, 1, 1, 0, 0, 0]);
+        assert_eq!(spec.released_at(u64::MAX), 8);
+    }
+
+    #[test]
+    fn no_halving_interval_keeps_reward_constant() {
+        let spec = spec(7, 0, u64::MAX);
+        assert_eq!(spec.block_reward(1), 7);
+        assert_eq!(spec.block_reward(1_000_000), 7);
+        assert_eq!(spec.released_at(1_000), 7_000);
+    }
+
+    #[test]
+    fn release_is_capped_by_reward_pool() {
+        // Quỹ 250: block 3 chỉ còn 50, sau đó không trả nữa
+        let spec = spec(100, 0, 250);
+        assert_eq!(spec.block_reward(2), 100);
+        assert_eq!(spec.block_reward(3), 50);
+        assert_eq!(spec.block_reward(4), 0);
+        assert_eq!(spec.released_at(u64::MAX), 250);
+    }
+}
@@ -1,69 +1,470 @@
+use crate::state::StateView;
+use crate::params::{ChainParams, ParamChange};
 use serde::{Serialize, Deserialize};
-use tokio::sync::RwLock;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize, Clone)]
+const NEXT_ID_KEY: &str = "gov:next_id";
+
+fn proposal_key(id: u64) -> String { format!("gov:proposal:{:020}", id) }
+fn vote_prefix(id: u64) -> String { format!("gov:vote:{:020}:", id) }
+fn ends_prefix(height: u64) -> String { format!("gov:ends:{:020}:", height) }
+fn activate_prefix(height: u64) -> String { format!("gov:activate:{:020}:", height) }
+fn voted_prefix(voter: &str) -> String { format!("gov:voted:{}:", voter) }
+fn delegate_key(delegator: &str) -> String { format!("gov:delegate:{}", delegator) }
+const DELEGATE_PREFIX: &str = "gov:delegate:";
+
+/// Tham số governance trong chain spec (`[governance]`), chụp lại vào mỗi đề xuất lúc tạo.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GovernanceParams {
+    /// Số block mở bỏ phiếu kể từ block chứa đề xuất.
+    pub voting_period: u64,
+    /// Stake đã bỏ phiếu tối thiểu, tính theo % tổng stake toàn mạng lúc kết thúc.
+    pub quorum_percent: u64,
+    /// Stake "yes" phải vượt ngưỡng này, tính theo % stake đã chọn yes/no (phiếu trắng không tính).
+    pub threshold_percent: u64,
+    /// Số block từ khi hết hạn bỏ phiếu tới khi thay đổi tham số được áp dụng, để node kịp chuẩn bị.
+    #[serde(default = "default_activation_delay")]
+    pub activation_delay: u64,
+}
+
+fn default_activation_delay() -> u64 {
+    // ~2 giờ với slot 800ms
+    10_000
+}
+
+impl Default for GovernanceParams {
+    fn default() -> Self {
+        // 756_000 block ~ 7 ngày với slot 800ms
+        Self { voting_period: 756_000, quorum_percent: 20, threshold_percent: 50, activation_delay: default_activation_delay() }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ProposalStatus {
+    Active,
+    Passed,
+    Rejected,
+    /// Đã thông qua và thay đổi tham số đã được áp dụng.
+    Executed,
+}
+
+/// Lựa chọn khi bỏ phiếu. Phiếu trắng tính vào quorum nhưng không tính vào ngưỡng thông qua.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum VoteChoice {
+    Yes,
+    No,
+    Abstain,
+}
+
+impl VoteChoice {
+    pub fn from_approve(approve: bool) -> Self {
+        if approve { VoteChoice::Yes } else { VoteChoice::No }
+    }
+}
+
+/// Kết quả kiểm phiếu theo stake (gồm stake được ủy quyền).
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Tally {
+    pub yes: u64,
+    pub no: u64,
+    pub abstain: u64,
+    pub total_staked: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Proposal {
     pub id: u64,
+    pub proposer: String,
     pub title: String,
     pub description: String,
-    pub votes_yes: u64,
-    pub votes_no: u64,
-    pub status: String, // "Active", "Passed", "Rejected"
+    pub start_height: u64,
+    pub end_height: u64,
+    pub quorum_percent: u64,
+    pub threshold_percent: u64,
+    pub status: ProposalStatus,
+    /// Thay đổi tham số chuỗi (nếu có) và height áp dụng khi được thông qua.
+    pub action: Option<ParamChange>,
+    pub activation_height: Option<u64>,
+    /// Kết quả chốt khi hết hạn; đề xuất đang mở thì API trả kết quả tạm tính.
+    pub tally: Tally,
 }
 
-pub struct NeuroDAO {
-    proposals: RwLock<HashMap<u64, Proposal>>,
-    next_id: RwLock<u64>,
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoteRecord {
+    pub choice: VoteChoice,
+    /// Height của lần bỏ phiếu gần nhất (phiếu được đổi tới hết hạn).
+    pub height: u64,
 }
 
+/// Một dòng lịch sử bỏ phiếu của một địa chỉ.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoteHistoryEntry {
+    pub proposal_id: u64,
+    pub title: String,
+    pub status: ProposalStatus,
+    pub choice: VoteChoice,
+    pub height: u64,
+}
+
+/// NeuroDAO on-chain: đề xuất, phiếu và ủy quyền là giao dịch đã ký, trạng thái nằm trong state (sled, có undo khi reorg).
+/// Mỗi địa chỉ một phiếu, đổi được tới hết hạn; trọng số là stake của địa chỉ đó tại block kết thúc bỏ phiếu, nên stake đã
+/// chuyển sang địa chỉ khác không thể được tính hai lần. Stake của người ủy quyền (một cấp, không bắc cầu) cộng vào phiếu
+/// của người được ủy quyền, trừ khi chính người ủy quyền đã bỏ phiếu.
+pub struct NeuroDAO;
+
 impl NeuroDAO {
-    pub fn new() -> Self {
-        println!("⚖️  NEURO DAO GOVERNANCE SYSTEM ONLINE");
-        Self {
-            proposals: RwLock::new(HashMap::new()),
-            next_id: RwLock::new(1),
-        }
+    pub fn get(view: &StateView, id: u64) -> Option<Proposal> {
+        view.get_data(&proposal_key(id)).and_then(|v| bincode::deserialize(&v).ok())
     }
 
-    pub async fn create_proposal(&self, title: String, desc: String) -> u64 {
-        let mut id_lock = self.next_id.write().await;
-        let id = *id_lock;
-        *id_lock += 1;
+    fn put(view: &mut StateView, proposal: &Proposal) {
+        view.set_data(&proposal_key(proposal.id), Some(bincode::serialize(proposal).unwrap()));
+    }
 
-        let prop = Proposal {
+    pub fn propose(view: &mut StateView, params: &GovernanceParams, proposer: &str, title: &str, description: &str, action: Option<ParamChange>) -> Result<u64, String> {
+        let id = view.get_data(NEXT_ID_KEY).map(|v| u64::from_be_bytes(v.try_into().unwrap())).unwrap_or(1);
+        let end_height = view.height + params.voting_period.max(1);
+        let activation_height = action.as_ref().map(|_| end_height + params.activation_delay.max(1));
+        Self::put(view, &Proposal {
             id,
-            title,
-            description: desc,
-            votes_yes: 0,
-            votes_no: 0,
-            status: "Active".to_string(),
-        };
+            proposer: proposer.to_string(),
+            title: title.to_string(),
+            description: description.to_string(),
+            start_height: view.height,
+            end_height,
+            quorum_percent: params.quorum_percent,
+            threshold_percent: params.threshold_percent,
+            status: ProposalStatus::Active,
+            action,
+            activation_height,
+            tally: Tally::default(),
+        });
+        view.set_data(NEXT_ID_KEY, Some((id + 1).to_be_bytes().to_vec()));
+        view.set_data(&format!("{}{:020}", ends_prefix(end_height), id), Some(Vec::new()));
+        Ok(id)
+    }
 
-        self.proposals.write().await.insert(id, prop);
-        id
+    /// Bỏ phiếu hoặc đổi phiếu đã bỏ, miễn đề xuất còn mở.
+    pub fn vote(view: &mut StateView, voter: &str, id: u64, choice: VoteChoice) -> Result<(), String> {
+        let proposal = Self::get(view, id).ok_or(format!("Không có đề xuất #{}", id))?;
+        if proposal.status != ProposalStatus::Active || view.height > proposal.end_height {
+            return Err(format!("Đề xuất #{} đã hết hạn bỏ phiếu", id));
+        }
+        let key = format!("{}{}", vote_prefix(id), voter);
+        if let Some(old) = view.get_data(&key).and_then(|v| bincode::deserialize::<VoteRecord>(&v).ok()) {
+            if old.choice == choice {
+                return Err(format!("{} đã chọn {:?} cho đề xuất #{}", voter, choice, id));
+            }
+        }
+        let record = VoteRecord { choice, height: view.height };
+        view.set_data(&key, Some(bincode::serialize(&record).unwrap()));
+        view.set_data(&format!("{}{:020}", voted_prefix(voter), id), Some(Vec::new()));
+        Ok(())
+    }
+
+    pub fn delegate_of(view: &StateView, delegator: &str) -> Option<String> {
+        view.get_data(&delegate_key(delegator)).and_then(|v| String::from_utf8(v).ok())
     }
 
-    pub async fn vote(&self, id: u64, approve: bool) -> Result<String, String> {
-        let mut props = self.proposals.write().await;
-        if let Some(p) = props.get_mut(&id) {
-            if p.status != "Active" {
-                return Err("Proposal is closed".to_string());
+    /// Ủy quyền biểu quyết cho `delegate` (thay ủy quyền cũ nếu có).
+    pub fn delegate(view: &mut StateView, delegator: &str, delegate: &str) -> Result<(), String> {
+        if delegator == delegate {
+            return Err("Không thể tự ủy quyền cho chính mình".to_string());
+        }
+        if Self::delegate_of(view, delegator).as_deref() == Some(delegate) {
+            return Err(format!("{} đã ủy quyền cho {}", delegator, delegate));
+        }
+        view.set_data(&delegate_key(delegator), Some(delegate.as_bytes().to_vec()));
+        Ok(())
+    }
+
+    pub fn undelegate(view: &mut StateView, delegator: &str) -> Result<(), String> {
+        if Self::delegate_of(view, delegator).is_none() {
+            return Err(format!("{} chưa ủy quyền cho ai", delegator));
+        }
+        view.set_data(&delegate_key(delegator), None);
+        Ok(())
+    }
+
+    /// Kiểm phiếu theo stake hiện tại của từng người bỏ phiếu, cộng stake của những người ủy quyền cho họ mà không tự bỏ phiếu.
+    pub fn tally(view: &StateView, id: u64) -> Tally {
+        let prefix = vote_prefix(id);
+        let votes: BTreeMap<String, VoteChoice> = view.scan_data(&prefix).into_iter()
+            .filter_map(|(key, value)| Some((key[prefix.len()..].to_string(), bincode::deserialize::<VoteRecord>(&value).ok()?.choice)))
+            .collect();
+        let mut weights: BTreeMap<&str, u64> = votes.keys().map(|voter| (voter.as_str(), view.get(voter).staked)).collect();
+        for (key, value) in view.scan_data(DELEGATE_PREFIX) {
+            let delegator = &key[DELEGATE_PREFIX.len()..];
+            if votes.contains_key(delegator) { continue; }
+            let delegate = String::from_utf8_lossy(&value);
+            if let Some(w) = weights.get_mut(delegate.as_ref()) { *w += view.get(delegator).staked; }
+        }
+        let mut tally = Tally { total_staked: view.total_staked(), ..Default::default() };
+        for (voter, weight) in weights {
+            match votes[voter] {
+                VoteChoice::Yes => tally.yes += weight,
+                VoteChoice::No => tally.no += weight,
+                VoteChoice::Abstain => tally.abstain += weight,
             }
-            if approve { p.votes_yes += 1; } else { p.votes_no += 1; }
-            
-            // Logic đơn giản: Nếu quá 10 phiếu thì chốt
-            if p.votes_yes + p.votes_no >= 10 {
-                p.status = if p.votes_yes > p.votes_no { "Passed".to_string() } else { "Rejected".to_string() };
+        }
+        tally
+    }
+
+    /// Các phiếu trực tiếp của `voter`, theo id đề xuất.
+    pub fn history(view: &StateView, voter: &str) -> Vec<VoteHistoryEntry> {
+        let prefix = voted_prefix(voter);
+        view.scan_data(&prefix).keys()
+            .filter_map(|k| k[prefix.len()..].parse::<u64>().ok())
+            .filter_map(|id| {
+                let proposal = Self::get(view, id)?;
+                let record: VoteRecord = bincode::deserialize(&view.get_data(&format!("{}{}", vote_prefix(id), voter))?).ok()?;
+                Some(VoteHistoryEntry { proposal_id: id, title: proposal.title, status: proposal.status, choice: record.choice, height: record.height })
+            })
+            .collect()
+    }
+
+    /// Gọi ở cuối mỗi block: chốt các đề xuất có `end_height` bằng height của block.
+    pub fn close_ended(view: &mut StateView) -> Vec<Proposal> {
+        let prefix = ends_prefix(view.height);
+        let ids: Vec<u64> = view.scan_data(&prefix).keys().filter_map(|k| k[prefix.len()..].parse().ok()).collect();
+        let mut closed = Vec::new();
+        for id in ids {
+            let mut proposal = match Self::get(view, id) { Some(p) => p, None => continue };
+            let tally = Self::tally(view, id);
+            let decided = tally.yes as u128 + tally.no as u128;
+            let voted = decided + tally.abstain as u128;
+            let quorum = voted * 100 >= proposal.quorum_percent as u128 * tally.total_staked as u128 && voted > 0;
+            let passed = quorum && tally.yes as u128 * 100 > proposal.threshold_percent as u128 * decided;
+            proposal.status = if passed { ProposalStatus::Passed } else { ProposalStatus::Rejected };
+            proposal.tally = tally;
+            Self::put(view, &proposal);
+            view.set_data(&format!("{}{:020}", prefix, id), None);
+            if let (true, Some(at)) = (passed, proposal.activation_height) {
+                view.set_data(&format!("{}{:020}", activate_prefix(at), id), Some(Vec::new()));
             }
-            Ok(format!("Voted. Current: {} Yes / {} No", p.votes_yes, p.votes_no))
-        } else {
-            Err("Proposal not found".to_string())
+            closed.push(proposal);
+        }
+        closed
+    }
+
+    /// Gọi ở cuối mỗi block (sau `close_ended`): áp các thay đổi tham số đã thông qua tới hạn ở height này,
+    /// theo thứ tự id. `slot` là slot của block, dùng khi đổi block interval.
+    pub fn activate_due(view: &mut StateView, slot: u64) -> Result<Vec<Proposal>, String> {
+        let prefix = activate_prefix(view.height);
+        let ids: Vec<u64> = view.scan_data(&prefix).keys().filter_map(|k| k[prefix.len()..].parse().ok()).collect();
+        if ids.is_empty() { return Ok(Vec::new()); }
+        let mut params = ChainParams::load(view)?;
+        let mut executed = Vec::new();
+        for id in ids {
+            view.set_data(&format!("{}{:020}", prefix, id), None);
+            let mut proposal = match Self::get(view, id) { Some(p) => p, None => continue };
+            if let Some(change) = &proposal.action { params.apply(change, slot); }
+            proposal.status = ProposalStatus::Executed;
+            Self::put(view, &proposal);
+            executed.push(proposal);
+        }
+        params.save(view);
+        Ok(executed)
+    }
+
+    /// Danh sách đề xuất; đề xuất đang mở kèm kết quả tạm tính.
+    pub fn list(view: &StateView) -> Vec<Proposal> {
+        view.scan_data("gov:proposal:").values()
+            .filter_map(|v| bincode::deserialize::<Proposal>(v).ok())
+            .map(|mut p| {
+                if p.status == ProposalStatus::Active { p.tally = Self::tally(view, p.id); }
+                p
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::{ChainSpec, EmissionSpec};
+    use crate::memstore::MemoryStore;
+    use crate::state::Account;
+    use crate::storage::ChainStore;
+
+    const A: &str = "PAPPAP000000000000000000000000000000000A";
+    const B: &str = "PAPPAP000000000000000000000000000000000B";
+    const C: &str = "PAPPAP000000000000000000000000000000000C";
+
+    fn params() -> GovernanceParams {
+        GovernanceParams { voting_period: 10, quorum_percent: 20, threshold_percent: 50, activation_delay: 5 }
+    }
+
+    /// Kho có sẵn block #0 với các địa chỉ đã stake theo `stakes`.
+    fn staked(stakes: &[(&str, u64)]) -> MemoryStore {
+        let spec = ChainSpec {
+            chain_id: "pappap-test".to_string(),
+            genesis_timestamp: 0,
+            block_interval_ms: 800,
+            validators: Vec::new(),
+            balances: BTreeMap::new(),
+            emission: EmissionSpec::default(),
+            governance: params(),
+        };
+        let accounts = stakes.iter().map(|(a, s)| (a.to_string(), Account { staked: *s, ..Default::default() })).collect();
+        let storage = MemoryStore::new();
+        storage.init_genesis(&spec.genesis_block(), &accounts, &spec.genesis_data());
+        storage
+    }
+
+    fn close_at(view: &mut StateView, height: u64) -> Vec<Proposal> {
+        view.height = height;
+        NeuroDAO::close_ended(view)
+    }
+
+    #[test]
+    fn tally_weighs_by_stake_and_counts_abstain_separately() {
+        let (storage, gov) = (staked(&[(A, 60), (B, 30), (C, 10)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let id = NeuroDAO::propose(&mut view, &gov, A, "t", "d", None).unwrap();
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::Yes).unwrap();
+        NeuroDAO::vote(&mut view, B, id, VoteChoice::No).unwrap();
+        NeuroDAO::vote(&mut view, C, id, VoteChoice::Abstain).unwrap();
+
+        let tally = NeuroDAO::tally(&view, id);
+        assert_eq!((tally.yes, tally.no, tally.abstain, tally.total_staked), (60, 30, 10, 100));
+        let closed = close_at(&mut view, 11);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].status, ProposalStatus::Passed);
+        assert_eq!(closed[0].tally.yes, 60);
+        assert_eq!(NeuroDAO::get(&view, id).unwrap().status, ProposalStatus::Passed);
+    }
+
+    #[test]
+    fn quorum_is_reached_exactly_at_threshold_stake() {
+        // Tổng stake 100, quorum 20%: 20 vừa đủ, 19 thiếu một đơn vị
+        let (storage, gov) = (staked(&[(A, 20), (B, 19), (C, 61)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let at = NeuroDAO::propose(&mut view, &gov, A, "at", "d", None).unwrap();
+        let below = NeuroDAO::propose(&mut view, &gov, B, "below", "d", None).unwrap();
+        NeuroDAO::vote(&mut view, A, at, VoteChoice::Yes).unwrap();
+        NeuroDAO::vote(&mut view, B, below, VoteChoice::Yes).unwrap();
+
+        let closed = close_at(&mut view, 11);
+        let status = |id: u64| closed.iter().find(|p| p.id == id).unwrap().status.clone();
+        assert_eq!(status(at), ProposalStatus::Passed);
+        assert_eq!(status(below), ProposalStatus::Rejected);
+    }
+
+    #[test]
+    fn abstain_counts_toward_quorum_but_not_threshold() {
+        // 19 yes thiếu quorum; thêm 1 phiếu trắng thì đủ 20 và yes chiếm 100% phiếu quyết định
+        let (storage, gov) = (staked(&[(A, 19), (B, 1), (C, 80)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let id = NeuroDAO::propose(&mut view, &gov, A, "t", "d", None).unwrap();
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::Yes).unwrap();
+        NeuroDAO::vote(&mut view, B, id, VoteChoice::Abstain).unwrap();
+        assert_eq!(close_at(&mut view, 11)[0].status, ProposalStatus::Passed);
+
+        let (storage, gov) = (staked(&[(A, 10), (B, 10), (C, 80)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let id = NeuroDAO::propose(&mut view, &gov, A, "t", "d", None).unwrap();
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::Abstain).unwrap();
+        NeuroDAO::vote(&mut view, B, id, VoteChoice::Abstain).unwrap();
+        let closed = close_at(&mut view, 11);
+        assert_eq!((closed[0].tally.abstain, closed[0].tally.yes), (20, 0));
+        assert_eq!(closed[0].status, ProposalStatus::Rejected);
+    }
+
+    #[test]
+    fn threshold_needs_strictly_more_than_half_of_decided_stake() {
+        let (storage, gov) = (staked(&[(A, 30), (B, 30), (C, 1)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let tie = NeuroDAO::propose(&mut view, &gov, A, "tie", "d", None).unwrap();
+        let over = NeuroDAO::propose(&mut view, &gov, A, "over", "d", None).unwrap();
+        NeuroDAO::vote(&mut view, A, tie, VoteChoice::Yes).unwrap();
+        NeuroDAO::vote(&mut view, B, tie, VoteChoice::No).unwrap();
+        for (voter, choice) in [(A, VoteChoice::Yes), (C, VoteChoice::Yes), (B, VoteChoice::No)] {
+            NeuroDAO::vote(&mut view, voter, over, choice).unwrap();
         }
+
+        let closed = close_at(&mut view, 11);
+        let status = |id: u64| closed.iter().find(|p| p.id == id).unwrap().status.clone();
+        assert_eq!(status(tie), ProposalStatus::Rejected);
+        assert_eq!(status(over), ProposalStatus::Passed);
+    }
+
+    #[test]
+    fn votes_accepted_through_end_height_only() {
+        let (storage, gov) = (staked(&[(A, 50), (B, 50)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let id = NeuroDAO::propose(&mut view, &gov, A, "t", "d", None).unwrap();
+        assert_eq!(NeuroDAO::get(&view, id).unwrap().end_height, 11);
+        assert!(close_at(&mut view, 10).is_empty());
+
+        // Block kết thúc vẫn nhận phiếu, block sau đó thì không
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::Yes).unwrap();
+        view.height = 11;
+        NeuroDAO::vote(&mut view, B, id, VoteChoice::No).unwrap();
+        assert_eq!(close_at(&mut view, 11)[0].status, ProposalStatus::Rejected);
+        view.height = 12;
+        assert!(NeuroDAO::vote(&mut view, B, id, VoteChoice::Yes).is_err());
     }
 
-    pub async fn list_proposals(&self) -> Vec<Proposal> {
-        let props = self.proposals.read().await;
-        props.values().cloned().collect()
+    #[test]
+    fn delegated_stake_follows_delegate_unless_delegator_votes() {
+        let (storage, gov) = (staked(&[(A, 10), (B, 15), (C, 75)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let id = NeuroDAO::propose(&mut view, &gov, A, "t", "d", None).unwrap();
+        NeuroDAO::delegate(&mut view, B, A).unwrap();
+
+        // Người được ủy quyền chưa bỏ phiếu thì stake ủy quyền không được tính
+        assert_eq!(NeuroDAO::tally(&view, id).yes, 0);
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::Yes).unwrap();
+        assert_eq!(NeuroDAO::tally(&view, id).yes, 25);
+
+        // B tự bỏ phiếu thì stake của B tách khỏi phiếu của A
+        NeuroDAO::vote(&mut view, B, id, VoteChoice::No).unwrap();
+        let tally = NeuroDAO::tally(&view, id);
+        assert_eq!((tally.yes, tally.no), (10, 15));
+        assert_eq!(close_at(&mut view, 11)[0].status, ProposalStatus::Rejected);
+    }
+
+    #[test]
+    fn delegation_is_one_level_and_can_be_moved_or_revoked() {
+        let (storage, gov) = (staked(&[(A, 10), (B, 20), (C, 30)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let id = NeuroDAO::propose(&mut view, &gov, A, "t", "d", None).unwrap();
+        assert!(NeuroDAO::delegate(&mut view, A, A).is_err());
+        NeuroDAO::delegate(&mut view, C, B).unwrap();
+        NeuroDAO::delegate(&mut view, B, A).unwrap();
+        assert!(NeuroDAO::delegate(&mut view, B, A).is_err());
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::Yes).unwrap();
+
+        // C -> B -> A không bắc cầu: A chỉ nhận stake của B
+        assert_eq!(NeuroDAO::tally(&view, id).yes, 30);
+        NeuroDAO::delegate(&mut view, C, A).unwrap();
+        assert_eq!(NeuroDAO::delegate_of(&view, C).as_deref(), Some(A));
+        assert_eq!(NeuroDAO::tally(&view, id).yes, 60);
+
+        NeuroDAO::undelegate(&mut view, B).unwrap();
+        assert!(NeuroDAO::undelegate(&mut view, B).is_err());
+        assert_eq!(NeuroDAO::tally(&view, id).yes, 40);
+    }
+
+    #[test]
+    fn changed_vote_moves_weight_and_updates_history() {
+        let (storage, gov) = (staked(&[(A, 40), (B, 60)]), params());
+        let mut view = StateView::new(&storage, 1, &gov);
+        let id = NeuroDAO::propose(&mut view, &gov, A, "t", "d", None).unwrap();
+        NeuroDAO::delegate(&mut view, B, A).unwrap();
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::Yes).unwrap();
+        assert!(NeuroDAO::vote(&mut view, A, id, VoteChoice::Yes).is_err());
+
+        view.height = 5;
+        NeuroDAO::vote(&mut view, A, id, VoteChoice::No).unwrap();
+        let tally = NeuroDAO::tally(&view, id);
+        assert_eq!((tally.yes, tally.no), (0, 100));
+
+        let history = NeuroDAO::history(&view, A);
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].proposal_id, history[0].choice, history[0].height), (id, VoteChoice::No, 5));
+        assert!(NeuroDAO::history(&view, B).is_empty());
+        assert_eq!(close_at(&mut view, 11)[0].status, ProposalStatus::Rejected);
     }
 }
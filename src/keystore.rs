@@ -0,0 +1,179 @@
+use crate::quantum::QuantumWallet;
+use crate::storage::ChainStore;
+use chacha20poly1305::{aead::{Aead, KeyInit}, ChaCha20Poly1305, Key, Nonce};
+use libp2p::{identity, PeerId};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Serialize, Deserialize};
+use sha2::Sha256;
+
+const KEYSTORE_VERSION: u8 = 1;
+const KDF_ROUNDS: u32 = 100_000;
+
+/// Bộ khóa của node: khóa libp2p (PeerId) và khóa Dilithium5 ký block/phiếu, luôn đi cùng nhau.
+#[derive(Serialize, Deserialize)]
+pub struct NodeKeys {
+    pub libp2p: Vec<u8>,
+    pub pqc_public: Vec<u8>,
+    pub pqc_secret: Vec<u8>,
+}
+
+// Dạng lưu trên đĩa / file export: ChaCha20-Poly1305 với khóa PBKDF2-SHA256 từ passphrase
+#[derive(Serialize, Deserialize)]
+struct EncryptedKeys {
+    version: u8,
+    salt: [u8; 16],
+    nonce: [u8; 12],
+    ciphertext: Vec<u8>,
+}
+
+/// Passphrase lấy từ PAPPAP_KEY_PASSPHRASE; không đặt thì khóa chỉ được mã hóa bằng passphrase rỗng.
+pub fn passphrase() -> String {
+    std::env::var("PAPPAP_KEY_PASSPHRASE").unwrap_or_else(|_| {
+        println!("⚠️  KEYSTORE: Chưa đặt PAPPAP_KEY_PASSPHRASE, khóa node KHÔNG được bảo vệ");
+        String::new()
+    })
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut key);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+impl NodeKeys {
+    pub fn generate(libp2p: Option<Vec<u8>>) -> Self {
+        let libp2p = libp2p.unwrap_or_else(|| identity::Keypair::generate_ed25519().to_protobuf_encoding().unwrap());
+        let (pqc_public, pqc_secret) = QuantumWallet::generate_keypair();
+        Self { libp2p, pqc_public, pqc_secret }
+    }
+
+    pub fn encrypt(&self, passphrase: &str) -> Vec<u8> {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut nonce);
+        let plain = bincode::serialize(self).unwrap();
+        let ciphertext = cipher(passphrase, &salt).encrypt(Nonce::from_slice(&nonce), plain.as_slice()).unwrap();
+        bincode::serialize(&EncryptedKeys { version: KEYSTORE_VERSION, salt, nonce, ciphertext }).unwrap()
+    }
+
+    pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Self, String> {
+        let enc: EncryptedKeys = bincode::deserialize(data).map_err(|e| format!("Keystore hỏng: {}", e))?;
+        if enc.version != KEYSTORE_VERSION {
+            return Err(format!("Keystore phiên bản {} không được hỗ trợ", enc.version));
+        }
+        let plain = cipher(passphrase, &enc.salt)
+            .decrypt(Nonce::from_slice(&enc.nonce), enc.ciphertext.as_slice())
+            .map_err(|_| "Sai passphrase hoặc keystore bị sửa".to_string())?;
+        bincode::deserialize(&plain).map_err(|e| format!("Keystore hỏng: {}", e))
+    }
+
+    /// Nạp khóa đã lưu; lần đầu thì tạo mới (giữ lại khóa libp2p cũ nếu DB đã có) và lưu dạng mã hóa.
+    pub fn load_or_create(storage: &dyn ChainStore, passphrase: &str) -> Result<Self, String> {
+        if let Some(data) = storage.load_keystore() {
+            return Self::decrypt(&data, passphrase);
+        }
+        let keys = Self::generate(storage.load_node_secret());
+        storage.save_keystore(&keys.encrypt(passphrase));
+        storage.remove_node_secret();
+        println!("🔑 KEYSTORE: Tạo khóa node mới");
+        Ok(keys)
+    }
+
+    pub fn libp2p_keypair(&self) -> identity::Keypair {
+        identity::Keypair::from_protobuf_encoding(&self.libp2p).expect("Khóa libp2p hỏng")
+    }
+
+    pub fn wallet(&self) -> QuantumWallet {
+        QuantumWallet::from_keys(self.pqc_public.clone(), self.pqc_secret.clone())
+    }
+}
+
+/// Ràng buộc tên node, PeerId và khóa Dilithium: mỗi khóa ký xác nhận khóa kia,
+/// nên không ai gắn được PeerId hay tên của mình vào khóa ký block của người khác.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NodeIdentity {
+    pub name: String,
+    pub peer_id: String,
+    pub pqc_pubkey: String,
+    pub pqc_signature: String,
+    pub peer_signature: String,
+}
+
+impl NodeIdentity {
+    fn payload(name: &str, peer_id: &str, pqc_pubkey: &str) -> Vec<u8> {
+        format!("pappap-identity:{}:{}:{}", name, peer_id, pqc_pubkey).into_bytes()
+    }
+
+    pub async fn bind(keys: &NodeKeys, wallet: &QuantumWallet, name: &str) -> Self {
+        let keypair = keys.libp2p_keypair();
+        let peer_id = keypair.public().to_peer_id().to_string();
+        let pqc_pubkey = hex::encode(&wallet.public_key);
+        let payload = Self::payload(name, &peer_id, &pqc_pubkey);
+        Self {
+            name: name.to_string(),
+            pqc_signature: hex::encode(wallet.sign_data(&payload).await),
+            peer_signature: hex::encode(keypair.sign(&payload).unwrap()),
+            peer_id,
+            pqc_pubkey,
+        }
+    }
+
+    pub fn verify(&self) -> bool {
+        let payload = Self::payload(&self.name, &self.peer_id, &self.pqc_pubkey);
+        let peer_key = self.peer_id.parse::<PeerId>().ok()
+            .and_then(|p| identity::PublicKey::try_decode_protobuf(p.as_ref().digest()).ok());
+        match (peer_key, hex::decode(&self.pqc_pubkey), hex::decode(&self.pqc_signature), hex::decode(&self.peer_signature)) {
+            (Some(pk), Ok(pqc), Ok(pqc_sig), Ok(peer_sig)) => {
+                pk.verify(&payload, &peer_sig) && QuantumWallet::verify_data(&payload, &pqc_sig, &pqc)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memstore::MemoryStore;
+
+    #[test]
+    fn encrypted_keys_open_only_with_their_passphrase() {
+        let keys = NodeKeys::generate(None);
+        let data = keys.encrypt("mật khẩu");
+        let opened = NodeKeys::decrypt(&data, "mật khẩu").unwrap();
+        assert_eq!((opened.libp2p, opened.pqc_public, opened.pqc_secret), (keys.libp2p.clone(), keys.pqc_public.clone(), keys.pqc_secret.clone()));
+        assert!(NodeKeys::decrypt(&data, "sai").is_err_and(|e| e.contains("Sai passphrase")));
+
+        let mut enc: EncryptedKeys = bincode::deserialize(&data).unwrap();
+        enc.ciphertext[0] ^= 1;
+        assert!(NodeKeys::decrypt(&bincode::serialize(&enc).unwrap(), "mật khẩu").is_err_and(|e| e.contains("bị sửa")));
+        enc.version = KEYSTORE_VERSION + 1;
+        assert!(NodeKeys::decrypt(&bincode::serialize(&enc).unwrap(), "mật khẩu").is_err_and(|e| e.contains("phiên bản")));
+        assert!(NodeKeys::decrypt(b"rac", "mật khẩu").is_err_and(|e| e.contains("hỏng")));
+    }
+
+    #[tokio::test]
+    async fn exported_keys_import_into_another_node_with_same_identity() {
+        let source = MemoryStore::new();
+        let keys = NodeKeys::load_or_create(&source, "p").unwrap();
+        // Lần sau đọc lại đúng khóa đã lưu, không tạo khóa mới
+        assert_eq!(NodeKeys::load_or_create(&source, "p").unwrap().pqc_public, keys.pqc_public);
+        assert!(NodeKeys::load_or_create(&source, "khác").is_err());
+
+        // File export là keystore mã hóa; node khác giải mã được thì ghi vào keystore của mình
+        let exported = keys.encrypt("p");
+        let target = MemoryStore::new();
+        target.save_keystore(&NodeKeys::decrypt(&exported, "p").unwrap().encrypt("p"));
+        let imported = NodeKeys::load_or_create(&target, "p").unwrap();
+        assert_eq!(imported.libp2p_keypair().public().to_peer_id(), keys.libp2p_keypair().public().to_peer_id());
+
+        let identity = NodeIdentity::bind(&imported, &imported.wallet(), "A").await;
+        assert_eq!(identity.pqc_pubkey, hex::encode(&keys.pqc_public));
+        assert!(identity.verify());
+        // Không gắn được tên hay khóa Dilithium khác vào PeerId này
+        assert!(!NodeIdentity { name: "B".to_string(), ..identity.clone() }.verify());
+        assert!(!NodeIdentity { pqc_pubkey: hex::encode(NodeKeys::generate(None).pqc_public), ..identity }.verify());
+    }
+}
@@ -4,9 +4,7 @@ use actix_files::NamedFile;
 use serde::Deserialize;
 use serde_json::json;
 use std::sync::Arc;
-use tokio::sync::Mutex;
 use std::env;
-use libp2p::identity;
 use tokio::time::{sleep, Duration};
 
 mod snn_core;
@@ -14,6 +12,7 @@ mod block;
 mod chain;
 mod ethics;
 mod storage;
+mod memstore;
 mod p2p;
 mod quantum;
 mod trainer;
@@ -25,6 +24,16 @@ mod governance;
 mod webnode;
 mod transaction;
 mod wallet;
+mod sync;
+mod state;
+mod genesis;
+mod merkle;
+mod consensus;
+mod finality;
+mod keystore;
+mod params;
+mod migrations;
+mod snapshot;
 
 use chain::PappapChain;
 use ethics::EthicsFilter;
@@ -32,114 +41,332 @@ use p2p::P2PNode;
 use trainer::AutoTrainer;
 use deploy::trigger_deploy;
 use governance::NeuroDAO;
-use storage::Storage;
+use state::StateView;
+use storage::{ChainStore, SledStore};
+use memstore::MemoryStore;
 use cache::SmartCache;
 use webnode::WebNodeManager;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use transaction::Transaction;
+use transaction::{Transaction, TxPayload, TX_VERSION, TX_VERSION_LEGACY};
+use sync::SyncState;
+use genesis::ChainSpec;
+use keystore::{NodeKeys, NodeIdentity};
+use params::emitted;
+use snapshot::Snapshot;
 
 #[derive(Deserialize)]
 struct PromptReq { prompt: String }
 #[derive(Deserialize)]
-struct TeachReq { keyword: String, answer: String }
-#[derive(Deserialize)]
 struct ComputeReq { client_id: String, data_payload: String }
 #[derive(Deserialize)]
-struct ProposalReq { title: String, desc: String }
+struct PingReq { client_id: String, hashrate: f32 }
+#[derive(Deserialize)]
+struct WorkerRegisterReq { client_id: String, address: String }
 #[derive(Deserialize)]
-struct VoteReq { id: u64, approve: bool }
+struct ChallengeReq { client_id: String }
 #[derive(Deserialize)]
-struct PingReq { client_id: String, hashrate: f32 }
+struct AnswerReq { client_id: String, challenge_id: String, answer: u64 }
 #[derive(Deserialize)]
-struct TxReq { sender: String, receiver: String, amount: u64, signature: String, id: String } 
+struct PageQuery { offset: Option<usize>, limit: Option<usize> }
+#[derive(Deserialize)]
+struct TxReq {
+    sender: String, fee: u64, nonce: u64, timestamp: i64, signature: String, id: Option<String>,
+    // Kiểu mới: version 2 + payload; kiểu cũ (version 1): chỉ receiver + amount
+    version: Option<u8>, payload: Option<TxPayload>,
+    receiver: Option<String>, amount: Option<u64>,
+}
 
 async fn get_blocks(data: web::Data<Arc<PappapChain>>) -> impl Responder {
     let recent = data.storage.get_recent_blocks(15);
     HttpResponse::Ok().json(recent)
 }
 async fn analyze_prompt(data: web::Data<Arc<PappapChain>>, req: web::Json<PromptReq>) -> impl Responder {
-    if let Err(e) = EthicsFilter::check(&req.prompt) { return HttpResponse::BadRequest().json(json!({"error": e})); }
+    if let Err(e) = EthicsFilter::check_with(&req.prompt, &data.params().ethics_blacklist) { return HttpResponse::BadRequest().json(json!({"error": e})); }
     let (score, mood, reply) = data.snn.process_text(&req.prompt).await;
     HttpResponse::Ok().json(json!({ "prompt": req.prompt, "mood": mood, "ai_response": reply, "spike": score }))
 }
-async fn teach_ai(data: web::Data<Arc<PappapChain>>, req: web::Json<TeachReq>) -> impl Responder {
-    if let Err(e) = EthicsFilter::check(&req.keyword) { return HttpResponse::BadRequest().json(json!({"error": e})); }
-    data.snn.learn(req.keyword.clone(), req.answer.clone()).await;
-    HttpResponse::Ok().json(json!({ "status": "Saved", "key": req.keyword }))
-}
 async fn compute_task(data: web::Data<Arc<PappapChain>>, req: web::Json<ComputeReq>) -> impl Responder {
-    if let Err(e) = EthicsFilter::check(&req.data_payload) { return HttpResponse::BadRequest().json(json!({"error": e})); }
+    if let Err(e) = EthicsFilter::check_with(&req.data_payload, &data.params().ethics_blacklist) { return HttpResponse::BadRequest().json(json!({"error": e})); }
     let (score, mood, _) = data.snn.process_text(&req.data_payload).await;
-    HttpResponse::Ok().json(json!({ "provider": "PAPPAP", "result": { "spike": score, "mood": mood }, "status": "SUCCESS" }))
+    HttpResponse::Ok().json(json!({ "provider": "PAPPAP", "client_id": req.client_id, "result": { "spike": score, "mood": mood }, "status": "SUCCESS" }))
 }
 async fn health(data: web::Data<Arc<PappapChain>>, wn: web::Data<Arc<WebNodeManager>>, pc: web::Data<Arc<AtomicUsize>>) -> impl Responder {
     let h = *data.height.read().await;
     let (n, p) = data.snn.stats().await;
     let stats = data.storage.load_stats();
-    let (wc, wp) = wn.get_stats().await;
-    HttpResponse::Ok().json(json!({ 
+    let (wc, wp, reported) = wn.get_stats().await;
+    let params = data.params();
+    let sync = match data.sync.state().await {
+        SyncState::Discovering { .. } => json!({ "status": "Discovering" }),
+        SyncState::Downloading { peer, target_height } => json!({ "status": "Downloading", "peer": peer.to_string(), "target_height": target_height }),
+        SyncState::NeedsSnapshot { peer, pruned_height } => json!({ "status": "NeedsSnapshot", "peer": peer.to_string(), "peer_pruned_height": pruned_height }),
+        SyncState::Synced => json!({ "status": "Synced" }),
+    };
+    let fault = data.fault();
+    let mut response = if fault.is_some() { HttpResponse::ServiceUnavailable() } else { HttpResponse::Ok() };
+    response.json(json!({ 
+        "healthy": fault.is_none(), "fault": fault,
         "height": h, "power": p + wp, "neurons": n + (wc * 100), 
-        "total_mined": stats.total_blocks, "web_nodes": wc, "p2p_nodes": pc.load(Ordering::Relaxed) 
+        "total_mined": stats.total_blocks, "web_nodes": wc, "web_reported_hashrate": reported, "p2p_nodes": pc.load(Ordering::Relaxed),
+        "chain_id": data.spec.chain_id, "genesis_hash": data.genesis_hash,
+        "finalized_height": data.storage.get_finalized().0,
+        "mempool": data.mempool.len(), "slot": data.schedule.current_slot(),
+        "validator": data.schedule.is_validator(&hex::encode(&data.wallet.public_key)),
+        "node_name": data.identity.name, "peer_id": data.identity.peer_id, "reward_address": data.reward_address,
+        "block_reward": params.block_reward(&data.spec, h + 1), "total_supply": data.spec.genesis_supply().to_string(), "rewards_paid": emitted(&*data.storage).map(|v| v.to_string()).unwrap_or_else(|e| e),
+        "params": params,
+        "sync": sync
     }))
 }
+async fn get_identity(data: web::Data<Arc<PappapChain>>) -> impl Responder {
+    HttpResponse::Ok().json(json!({ "identity": data.identity, "valid": data.identity.verify() }))
+}
+async fn get_account(data: web::Data<Arc<PappapChain>>, path: web::Path<String>) -> impl Responder {
+    let acct = data.storage.get_account(&path).unwrap_or_default();
+    HttpResponse::Ok().json(json!({ "address": path.into_inner(), "balance": acct.balance, "nonce": acct.nonce, "staked": acct.staked }))
+}
+/// Block theo height (số) hoặc hash; block nhánh phụ vẫn trả về với `canonical: false`.
+async fn get_block(data: web::Data<Arc<PappapChain>>, path: web::Path<String>) -> impl Responder {
+    let id = path.into_inner();
+    let block = match id.parse::<u64>() {
+        Ok(height) => data.storage.get_block(height),
+        Err(_) => data.storage.get_canonical_height(&id).and_then(|h| data.storage.get_block(h)).or_else(|| data.storage.get_block_by_hash(&id)),
+    };
+    let block = match block {
+        Some(b) => b,
+        None => return HttpResponse::NotFound().json(json!({"error": "Block not found"})),
+    };
+    let height = *data.height.read().await;
+    let canonical = data.storage.get_canonical_height(&block.hash) == Some(block.index);
+    HttpResponse::Ok().json(json!({
+        "canonical": canonical, "finalized": canonical && block.index <= data.storage.get_finalized().0,
+        "confirmations": if canonical { height.saturating_sub(block.index) + 1 } else { 0 }, "block": block
+    }))
+}
+/// Giao dịch theo id: đã vào chuỗi chính (kèm vị trí) hoặc đang chờ trong mempool.
+async fn get_tx(data: web::Data<Arc<PappapChain>>, path: web::Path<String>) -> impl Responder {
+    if let Some(loc) = data.storage.get_tx_location(&path) {
+        if let Some(block) = data.storage.get_block(loc.height) {
+            let height = *data.height.read().await;
+            return HttpResponse::Ok().json(json!({
+                "status": "Confirmed", "height": loc.height, "position": loc.position, "block_hash": block.hash,
+                "confirmations": height.saturating_sub(loc.height) + 1, "tx": block.transactions.get(loc.position as usize)
+            }));
+        }
+    }
+    match data.mempool.get(&path) {
+        Some(tx) => HttpResponse::Ok().json(json!({ "status": "Pending", "tx": tx })),
+        None => HttpResponse::NotFound().json(json!({"error": "Transaction not found"})),
+    }
+}
+async fn get_address_txs(data: web::Data<Arc<PappapChain>>, path: web::Path<String>, query: web::Query<PageQuery>) -> impl Responder {
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let (total, page) = data.storage.get_address_txs(&path, offset, limit);
+    let txs: Vec<_> = page.into_iter().map(|(loc, tx)| json!({ "height": loc.height, "position": loc.position, "tx": tx })).collect();
+    HttpResponse::Ok().json(json!({ "address": path.into_inner(), "total": total, "offset": offset, "limit": limit, "txs": txs }))
+}
+async fn tx_proof(data: web::Data<Arc<PappapChain>>, path: web::Path<(u64, String)>) -> impl Responder {
+    let (height, tx_id) = path.into_inner();
+    let block = match data.storage.get_block(height) {
+        Some(b) => b,
+        None => return HttpResponse::NotFound().json(json!({"error": "Block not found"})),
+    };
+    match merkle::merkle_proof(&block.transactions, &tx_id) {
+        Some(proof) => {
+            let tx = &block.transactions[proof.index];
+            HttpResponse::Ok().json(json!({
+                "block_hash": block.hash, "height": block.index, "tx_root": block.tx_root,
+                "valid": merkle::verify_proof(tx, &proof, &block.tx_root), "tx": tx, "proof": proof
+            }))
+        }
+        None => HttpResponse::NotFound().json(json!({"error": "Transaction not in block"})),
+    }
+}
 async fn index() -> impl Responder { NamedFile::open_async("./static/index.html").await }
-async fn create_proposal(dao: web::Data<Arc<NeuroDAO>>, req: web::Json<ProposalReq>) -> impl Responder {
-    let id = dao.create_proposal(req.title.clone(), req.desc.clone()).await;
-    HttpResponse::Ok().json(json!({ "status": "Created", "id": id }))
+async fn list_proposals(data: web::Data<Arc<PappapChain>>) -> impl Responder {
+    let view = StateView::new(&*data.storage, *data.height.read().await, &data.spec.governance);
+    HttpResponse::Ok().json(NeuroDAO::list(&view))
 }
-async fn vote_proposal(dao: web::Data<Arc<NeuroDAO>>, req: web::Json<VoteReq>) -> impl Responder {
-    match dao.vote(req.id, req.approve).await { Ok(m) => HttpResponse::Ok().json(m), Err(e) => HttpResponse::BadRequest().json(e) }
+async fn voting_history(data: web::Data<Arc<PappapChain>>, path: web::Path<String>) -> impl Responder {
+    let view = StateView::new(&*data.storage, *data.height.read().await, &data.spec.governance);
+    HttpResponse::Ok().json(json!({
+        "address": path.as_str(), "delegate": NeuroDAO::delegate_of(&view, &path), "votes": NeuroDAO::history(&view, &path)
+    }))
 }
-async fn list_proposals(dao: web::Data<Arc<NeuroDAO>>) -> impl Responder { HttpResponse::Ok().json(dao.list_proposals().await) }
 async fn webnode_ping(wn: web::Data<Arc<WebNodeManager>>, req: web::Json<PingReq>) -> impl Responder {
     wn.register_beat(req.client_id.clone(), req.hashrate).await;
     HttpResponse::Ok().json(json!({ "status": "Ack" }))
 }
+async fn webnode_register(wn: web::Data<Arc<WebNodeManager>>, req: web::Json<WorkerRegisterReq>) -> impl Responder {
+    match wn.register(req.client_id.clone(), req.address.clone()).await {
+        Ok(()) => HttpResponse::Ok().json(json!({ "status": "Registered", "address": req.address })),
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e })),
+    }
+}
+async fn webnode_challenge(wn: web::Data<Arc<WebNodeManager>>, req: web::Json<ChallengeReq>) -> impl Responder {
+    match wn.issue_challenge(req.client_id.clone()).await {
+        Ok(c) => HttpResponse::Ok().json(c),
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e })),
+    }
+}
+// Lời giải đúng trả về payload WebWork; worker tự ký, trả phí và gửi qua /api/tx/submit, node không ký thay
+async fn webnode_answer(wn: web::Data<Arc<WebNodeManager>>, req: web::Json<AnswerReq>) -> impl Responder {
+    match wn.submit_answer(&req.client_id, &req.challenge_id, req.answer).await {
+        Ok(payload) => HttpResponse::Ok().json(json!({ "status": "Verified", "payload": payload })),
+        Err(e) => HttpResponse::BadRequest().json(json!({ "error": e })),
+    }
+}
 async fn submit_tx(data: web::Data<Arc<PappapChain>>, req: web::Json<TxReq>) -> impl Responder {
-    let tx = Transaction {
-        id: req.id.clone(), sender: req.sender.clone(), receiver: req.receiver.clone(),
-        amount: req.amount, fee: 100, nonce: 0, timestamp: 0, signature: req.signature.clone()
+    let (version, payload) = match (&req.payload, &req.receiver, req.amount) {
+        (Some(p), _, _) => (req.version.unwrap_or(TX_VERSION), p.clone()),
+        (None, Some(receiver), Some(amount)) => (req.version.unwrap_or(TX_VERSION_LEGACY), TxPayload::Transfer { receiver: receiver.clone(), amount }),
+        _ => return HttpResponse::BadRequest().json(json!({"status": "Rejected", "error": "Thiếu payload hoặc receiver/amount"})),
     };
-    if data.mempool.add_tx(tx) { HttpResponse::Ok().json(json!({"status":"Success"})) } else { HttpResponse::BadRequest().json(json!({"status":"Fail"})) }
+    let mut tx = Transaction {
+        id: String::new(), version, sender: req.sender.clone(), nonce: req.nonce, fee: req.fee,
+        timestamp: req.timestamp, payload, signature: req.signature.clone()
+    };
+    // id luôn do node tính lại; nếu client gửi kèm id thì phải khớp
+    tx.id = req.id.clone().unwrap_or_else(|| tx.calculate_hash());
+    submit_and_broadcast(&data, tx)
+}
+
+// Vào mempool rồi phát cho peer; giao dịch bị từ chối trả nguyên lý do
+fn submit_and_broadcast(data: &PappapChain, tx: Transaction) -> HttpResponse {
+    let id = tx.id.clone();
+    let encoded = bincode::serialize(&tx).unwrap();
+    match data.submit_tx(tx) {
+        Ok(()) => {
+            data.p2p.broadcast_tx(encoded);
+            HttpResponse::Ok().json(json!({"status": "Success", "id": id}))
+        }
+        Err(reject) => HttpResponse::BadRequest().json(json!({"status": "Rejected", "error": reject})),
+    }
+}
+
+fn chainspec_path() -> String {
+    env::var("PAPPAP_CHAINSPEC").unwrap_or_else(|_| "chainspec.toml".to_string())
+}
+
+// Lệnh dòng lệnh (chạy xong thì thoát, không khởi động node):
+// `export-key <file>` / `import-key <file> [--force]`: file export vẫn được mã hóa bằng PAPPAP_KEY_PASSPHRASE,
+// import phải giải mã được mới ghi vào keystore.
+// `export-snapshot <file>` / `import-snapshot <file> [--hash <hash block đã chốt>]`: import chỉ vào pappap_data trống,
+// snapshot phải khớp chain spec và được QC trong snapshot hoặc hash tin cậy xác nhận.
+fn node_command(storage: &dyn ChainStore, args: &[String]) -> Result<(), String> {
+    let path = args.get(2).ok_or("Thiếu đường dẫn file")?;
+    match args[1].as_str() {
+        "export-key" => {
+            let pass = keystore::passphrase();
+            let keys = NodeKeys::load_or_create(storage, &pass)?;
+            std::fs::write(path, hex::encode(keys.encrypt(&pass))).map_err(|e| e.to_string())?;
+            println!("🔑 Đã export khóa node (PQC {}...) ra {}", &hex::encode(&keys.pqc_public)[..16], path);
+        }
+        "import-key" => {
+            let pass = keystore::passphrase();
+            let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+            let data = hex::decode(data.trim()).map_err(|_| "File khóa không phải hex".to_string())?;
+            let keys = NodeKeys::decrypt(&data, &pass)?;
+            if storage.load_keystore().is_some() && args.get(3).map(String::as_str) != Some("--force") {
+                return Err("Node đã có khóa, thêm --force để ghi đè".to_string());
+            }
+            storage.save_keystore(&keys.encrypt(&pass));
+            println!("🔑 Đã import khóa node (PQC {}...)", &hex::encode(&keys.pqc_public)[..16]);
+        }
+        "export-snapshot" => {
+            let spec = ChainSpec::load(&chainspec_path())?;
+            storage.check_consistency()?;
+            let snap = Snapshot::capture(storage, &spec)?;
+            snap.save(path)?;
+            println!("📸 Đã export snapshot block #{} ({}) ra {}", snap.block.index, snap.block.hash, path);
+        }
+        "import-snapshot" => {
+            let spec = ChainSpec::load(&chainspec_path())?;
+            let trusted = match args.get(3).map(String::as_str) {
+                Some("--hash") => Some(args.get(4).ok_or("Thiếu hash sau --hash")?.as_str()),
+                Some(other) => return Err(format!("Tham số không hợp lệ: {}", other)),
+                None => None,
+            };
+            let snap = Snapshot::load(path)?;
+            snap.verify(&spec, trusted)?;
+            storage.restore_snapshot(&spec.genesis_block(), &snap)?;
+            println!("📸 Đã nạp snapshot block #{} ({}), {} tài khoản", snap.block.index, snap.block.hash, snap.accounts.len());
+        }
+        other => return Err(format!("Lệnh không hợp lệ: {}", other)),
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
-    let storage = Arc::new(Storage::new("pappap_data"));
+    // PAPPAP_STORAGE=memory: node tạm, toàn bộ chuỗi và khóa mất khi dừng
+    let storage: Arc<dyn ChainStore> = if env::var("PAPPAP_STORAGE").as_deref() == Ok("memory") {
+        println!("💾 STORAGE: Chạy trong RAM (PAPPAP_STORAGE=memory), dữ liệu không được lưu");
+        Arc::new(MemoryStore::new())
+    } else {
+        match SledStore::new("pappap_data") {
+            Ok(store) => Arc::new(store),
+            Err(e) => { eprintln!("❌ {}", e); std::process::exit(1); }
+        }
+    };
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 {
+        if let Err(e) = node_command(&*storage, &args) { eprintln!("❌ {}", e); std::process::exit(1); }
+        return Ok(());
+    }
     let mut stats = storage.load_stats();
     stats.total_starts += 1;
     storage.save_stats(&stats);
 
-    let local_key = if let Some(kb) = storage.load_node_secret() { identity::Keypair::from_protobuf_encoding(&kb).expect("Key Error") } else { let k = identity::Keypair::generate_ed25519(); storage.save_node_secret(&k.to_protobuf_encoding().unwrap()); k };
+    let keys = NodeKeys::load_or_create(&*storage, &keystore::passphrase()).unwrap_or_else(|e| panic!("Không mở được keystore: {}", e));
+    let local_key = keys.libp2p_keypair();
+    let wallet = keys.wallet();
 
     let cache = SmartCache::new();
-    let dao = Arc::new(NeuroDAO::new());
-    let wn_mgr = Arc::new(WebNodeManager::new());
     let p_count = Arc::new(AtomicUsize::new(0));
 
-    let (p2p_node, _, pid) = P2PNode::new(local_key, p_count.clone()).await.unwrap();
-    let p2p_arc = Arc::new(Mutex::new(p2p_node));
-    let p2p_run = p2p_arc.clone();
-    let p2p_chain = p2p_arc.clone(); // Clone d? truy?n vào chain
+    // Địa chỉ P2P: PAPPAP_P2P_LISTEN="/ip4/0.0.0.0/tcp/9000" (mặc định)
+    let listen = env::var("PAPPAP_P2P_LISTEN").unwrap_or_else(|_| "/ip4/0.0.0.0/tcp/9000".to_string());
+    let (mut p2p_node, net_rx, pid) = P2PNode::new(local_key, p_count.clone(), &listen).await.unwrap();
+    let p2p_handle = p2p_node.handle();
+
+    // Bootnode: PAPPAP_PEERS="/ip4/1.2.3.4/tcp/9000,/ip4/5.6.7.8/tcp/9000"
+    if let Ok(peers) = env::var("PAPPAP_PEERS") {
+        for addr in peers.split(',').map(str::trim).filter(|a| !a.is_empty()) { p2p_handle.dial(addr); }
+    }
 
-    // S?A L?I: Truy?n d? 3 tham s? (storage, cache, p2p_chain)
-    let chain = Arc::new(PappapChain::new(storage.clone(), cache, p2p_chain).await);
+    let spec = ChainSpec::load(&chainspec_path()).expect("Chain spec lỗi");
+    // Tên node: PAPPAP_NODE_NAME, nếu không thì tên validator của khóa này trong chain spec
+    let pqc_hex = hex::encode(&wallet.public_key);
+    let node_name = env::var("PAPPAP_NODE_NAME").ok()
+        .or_else(|| spec.validators.iter().find(|v| v.pqc_pubkey == pqc_hex).map(|v| v.name.clone()))
+        .unwrap_or_else(|| { let p = pid.to_string(); format!("Pappap-Node-{}", &p[p.len() - 6..]) });
+    let identity = NodeIdentity::bind(&keys, &wallet, &node_name).await;
+    drop(keys);
+    let chain = Arc::new(PappapChain::new(storage.clone(), cache, p2p_handle, spec, wallet, identity).await);
+    let wn_mgr = Arc::new(WebNodeManager::new(storage.clone(), chain.wallet.clone(), chain.height.clone()));
     
     let m_chain = chain.clone();
     let t_snn = chain.snn.clone();
+    let c_snn = chain.snn.clone();
     let wn_run = wn_mgr.clone();
+    let mp_run = chain.mempool.clone();
 
     println!("?? NODE STARTED | PID: {}", pid);
 
-    tokio::spawn(async move { let mut n = p2p_run.lock().await; n.run().await; });
+    let net_chain = chain.clone();
+    tokio::spawn(async move { p2p_node.run().await; });
+    tokio::spawn(async move { net_chain.handle_network(net_rx).await; });
     tokio::spawn(async move { m_chain.run().await; });
     tokio::spawn(async move { AutoTrainer::start(t_snn).await; });
     tokio::spawn(async move { loop { sleep(Duration::from_secs(10)).await; wn_run.prune_offline().await; } });
+    tokio::spawn(async move { loop { sleep(Duration::from_secs(30)).await; mp_run.expire(); } });
+    tokio::spawn(async move { loop { sleep(Duration::from_secs(600)).await; c_snn.prune_cache().await; } });
 
     HttpServer::new(move || {
         App::new()
             .app_data(web::Data::new(chain.clone()))
-            .app_data(web::Data::new(dao.clone()))
             .app_data(web::Data::new(wn_mgr.clone()))
             .app_data(web::Data::new(p_count.clone()))
             .wrap(Cors::permissive())
@@ -147,14 +374,21 @@ async fn main() -> std::io::Result<()> {
             .route("/api/health", web::get().to(health))
             .route("/api/blocks", web::get().to(get_blocks))
             .route("/api/prompt", web::post().to(analyze_prompt))
-            .route("/api/teach", web::post().to(teach_ai))
             .route("/api/compute", web::post().to(compute_task))
             .route("/api/deploy", web::post().to(trigger_deploy))
-            .route("/api/dao/create", web::post().to(create_proposal))
-            .route("/api/dao/vote", web::post().to(vote_proposal))
             .route("/api/dao/list", web::get().to(list_proposals))
+            .route("/api/dao/votes/{addr}", web::get().to(voting_history))
             .route("/api/webnode/ping", web::post().to(webnode_ping))
+            .route("/api/webnode/register", web::post().to(webnode_register))
+            .route("/api/webnode/challenge", web::post().to(webnode_challenge))
+            .route("/api/webnode/answer", web::post().to(webnode_answer))
             .route("/api/tx/submit", web::post().to(submit_tx))
+            .route("/api/identity", web::get().to(get_identity))
+            .route("/api/account/{addr}", web::get().to(get_account))
+            .route("/api/block/{height}/proof/{tx_id}", web::get().to(tx_proof))
+            .route("/api/block/{id}", web::get().to(get_block))
+            .route("/api/tx/{id}", web::get().to(get_tx))
+            .route("/api/address/{addr}/txs", web::get().to(get_address_txs))
     })
     .bind(("0.0.0.0", 8080))?
     .run()
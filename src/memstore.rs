@@ -0,0 +1,299 @@
+use crate::block::Block;
+use crate::finality::{QuorumCertificate, VoteKind};
+use crate::snapshot::Snapshot;
+use crate::state::{Account, StateDiff, StateSummary, StateUndo};
+use crate::storage::{ChainStore, NodeStats, TxLocation};
+use crate::transaction::Transaction;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+#[derive(Default)]
+struct Inner {
+    node_secret: Option<Vec<u8>>,
+    keystore: Option<Vec<u8>>,
+    stats: Option<NodeStats>,
+    // Chuỗi chính theo height; mọi block đã biết (kể cả nhánh phụ) theo hash kèm tổng công
+    canonical: BTreeMap<u64, Block>,
+    blocks: HashMap<String, (Block, f64)>,
+    undo: HashMap<String, StateUndo>,
+    tip: (u64, String),
+    finalized: (u64, String),
+    qcs: BTreeMap<u64, QuorumCertificate>,
+    last_signed: HashMap<VoteKind, (u64, String)>,
+    pruned_height: u64,
+    hash_index: HashMap<String, u64>,
+    tx_index: HashMap<String, TxLocation>,
+    address_index: BTreeMap<(String, u64, u32), String>,
+    accounts: BTreeMap<String, Account>,
+    data: BTreeMap<String, Vec<u8>>,
+    mempool: BTreeMap<String, (Transaction, u64)>,
+    summary: Option<Arc<StateSummary>>,
+}
+
+impl Inner {
+    fn index_block(&mut self, block: &Block, insert: bool) {
+        if insert { self.hash_index.insert(block.hash.clone(), block.index); } else { self.hash_index.remove(&block.hash); }
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let position = position as u32;
+            if insert { self.tx_index.insert(tx.id.clone(), TxLocation { height: block.index, position }); } else { self.tx_index.remove(&tx.id); }
+            for addr in tx.addresses() {
+                let key = (addr, block.index, position);
+                if insert { self.address_index.insert(key, tx.id.clone()); } else { self.address_index.remove(&key); }
+            }
+        }
+    }
+
+    fn write_data(&mut self, key: &str, value: &Option<Vec<u8>>) {
+        match value {
+            Some(v) => { self.data.insert(key.to_string(), v.clone()); }
+            None => { self.data.remove(key); }
+        }
+    }
+}
+
+/// Kho trong RAM: không ghi đĩa, mất hết khi dừng. Mỗi thao tác chạy trọn dưới một khóa nên luôn nguyên tử;
+/// dùng cho node tạm (PAPPAP_STORAGE=memory) và chạy nhiều node trong một process.
+#[derive(Default)]
+pub struct MemoryStore {
+    inner: Mutex<Inner>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn load_node_secret(&self) -> Option<Vec<u8>> {
+        self.lock().node_secret.clone()
+    }
+
+    fn remove_node_secret(&self) {
+        self.lock().node_secret = None;
+    }
+
+    fn save_keystore(&self, data: &[u8]) {
+        self.lock().keystore = Some(data.to_vec());
+    }
+
+    fn load_keystore(&self) -> Option<Vec<u8>> {
+        self.lock().keystore.clone()
+    }
+
+    fn load_stats(&self) -> NodeStats {
+        self.lock().stats.clone().unwrap_or_else(NodeStats::fresh)
+    }
+
+    fn save_stats(&self, stats: &NodeStats) {
+        self.lock().stats = Some(stats.clone());
+    }
+
+    fn commit_block(&self, block: &Block, diff: &StateDiff) {
+        let mut inner = self.lock();
+        for (addr, acct) in &diff.after { inner.accounts.insert(addr.clone(), acct.clone()); }
+        for (key, value) in &diff.data_after { inner.write_data(key, value); }
+        inner.undo.insert(block.hash.clone(), StateUndo { accounts: diff.before.clone(), data: diff.data_before.clone() });
+        inner.canonical.insert(block.index, block.clone());
+        inner.tip = (block.index, block.hash.clone());
+        inner.index_block(block, true);
+        inner.stats.get_or_insert_with(NodeStats::fresh).total_blocks += 1;
+        inner.summary = inner.summary.take().filter(|s| s.tip == block.prev_hash).map(|s| {
+            let mut s = Arc::unwrap_or_clone(s);
+            s.apply(&block.hash, diff);
+            Arc::new(s)
+        });
+    }
+
+    fn disconnect_tip(&self, block: &Block) {
+        let mut inner = self.lock();
+        inner.summary = None;
+        if let Some(undo) = inner.undo.remove(&block.hash) {
+            for (addr, acct) in undo.accounts {
+                match acct {
+                    Some(a) => { inner.accounts.insert(addr, a); }
+                    None => { inner.accounts.remove(&addr); }
+                }
+            }
+            for (key, value) in &undo.data { inner.write_data(key, value); }
+        }
+        inner.canonical.remove(&block.index);
+        inner.tip = (block.index.saturating_sub(1), block.prev_hash.clone());
+        inner.index_block(block, false);
+    }
+
+    // Không có ghi dở trong RAM, chỉ còn đối chiếu state root với block đỉnh
+    fn check_consistency(&self) -> Result<(), String> {
+        let Some(block) = self.get_block(self.get_height()) else { return Ok(()); };
+        if self.state_root() != block.state_root {
+            return Err(format!("Trạng thái không khớp state_root của block đỉnh #{}", block.index));
+        }
+        Ok(())
+    }
+
+    fn get_block(&self, index: u64) -> Option<Block> {
+        self.lock().canonical.get(&index).cloned()
+    }
+
+    fn get_height(&self) -> u64 {
+        self.lock().tip.0
+    }
+
+    fn get_last_hash(&self) -> String {
+        self.lock().tip.1.clone()
+    }
+
+    fn set_finalized(&self, qc: &QuorumCertificate) {
+        let mut inner = self.lock();
+        inner.qcs.insert(qc.height, qc.clone());
+        inner.finalized = (qc.height, qc.block_hash.clone());
+    }
+
+    fn get_qc(&self, height: u64) -> Option<QuorumCertificate> {
+        self.lock().qcs.get(&height).cloned()
+    }
+
+    fn get_finalized(&self) -> (u64, String) {
+        self.lock().finalized.clone()
+    }
+
+    fn get_last_signed(&self, kind: VoteKind) -> Option<(u64, String)> {
+        self.lock().last_signed.get(&kind).cloned()
+    }
+
+    fn set_last_signed(&self, kind: VoteKind, height: u64, hash: &str) {
+        self.lock().last_signed.insert(kind, (height, hash.to_string()));
+    }
+
+    fn store_block(&self, block: &Block, total_work: f64) {
+        self.lock().blocks.insert(block.hash.clone(), (block.clone(), total_work));
+    }
+
+    fn get_block_by_hash(&self, hash: &str) -> Option<Block> {
+        self.lock().blocks.get(hash).map(|(b, _)| b.clone())
+    }
+
+    fn get_total_work(&self, hash: &str) -> Option<f64> {
+        self.lock().blocks.get(hash).map(|(_, w)| *w)
+    }
+
+    fn forget_block(&self, hash: &str) {
+        self.lock().blocks.remove(hash);
+    }
+
+    fn get_canonical_height(&self, hash: &str) -> Option<u64> {
+        self.lock().hash_index.get(hash).copied()
+    }
+
+    fn get_tx_location(&self, id: &str) -> Option<TxLocation> {
+        self.lock().tx_index.get(id).copied()
+    }
+
+    fn get_address_txs(&self, address: &str, offset: usize, limit: usize) -> (usize, Vec<(TxLocation, Transaction)>) {
+        let inner = self.lock();
+        let range = (address.to_string(), 0, 0)..=(address.to_string(), u64::MAX, u32::MAX);
+        let total = inner.address_index.range(range.clone()).count();
+        let page = inner.address_index.range(range).rev()
+            .skip(offset)
+            .take(limit)
+            .filter_map(|(_, id)| {
+                let location = *inner.tx_index.get(id)?;
+                let tx = inner.canonical.get(&location.height)?.transactions.get(location.position as usize)?.clone();
+                Some((location, tx))
+            })
+            .collect();
+        (total, page)
+    }
+
+    fn get_pruned_height(&self) -> u64 {
+        self.lock().pruned_height
+    }
+
+    fn get_undo(&self, hash: &str) -> Option<StateUndo> {
+        self.lock().undo.get(hash).cloned()
+    }
+
+    fn prune_below(&self, keep_from: u64) -> u64 {
+        let mut inner = self.lock();
+        let mut pruned = 0;
+        for i in (inner.pruned_height + 1)..keep_from.min(inner.tip.0) {
+            if let Some(b) = inner.canonical.remove(&i) {
+                inner.blocks.remove(&b.hash);
+                inner.undo.remove(&b.hash);
+                inner.index_block(&b, false);
+            }
+            inner.qcs.remove(&i);
+            inner.pruned_height = i;
+            pruned += 1;
+        }
+        let pruned_height = inner.pruned_height;
+        inner.blocks.retain(|_, (b, _)| b.index == 0 || b.index > pruned_height);
+        pruned
+    }
+
+    fn restore_snapshot(&self, genesis: &Block, snap: &Snapshot) -> Result<(), String> {
+        let mut inner = self.lock();
+        if !inner.canonical.is_empty() || inner.tip.0 > 0 {
+            return Err("Kho đã có dữ liệu chuỗi, chỉ nạp snapshot vào kho trống".to_string());
+        }
+        let tip = &snap.block;
+        for (block, work) in [(genesis, 0.0f64), (tip, snap.total_work)] {
+            inner.canonical.insert(block.index, block.clone());
+            inner.blocks.insert(block.hash.clone(), (block.clone(), work));
+            inner.index_block(block, true);
+        }
+        inner.accounts = snap.accounts.clone();
+        inner.summary = None;
+        inner.data = snap.data.clone();
+        if let Some(qc) = &snap.qc { inner.qcs.insert(qc.height, qc.clone()); }
+        inner.finalized = (tip.index, tip.hash.clone());
+        inner.pruned_height = tip.index.saturating_sub(1);
+        inner.tip = (tip.index, tip.hash.clone());
+        Ok(())
+    }
+
+    fn get_account(&self, address: &str) -> Option<Account> {
+        self.lock().accounts.get(address).cloned()
+    }
+
+    fn load_accounts(&self) -> Vec<(String, Account)> {
+        self.lock().accounts.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    fn get_state_data(&self, key: &str) -> Option<Vec<u8>> {
+        self.lock().data.get(key).cloned()
+    }
+
+    fn scan_state_data(&self, prefix: &str) -> Vec<(String, Vec<u8>)> {
+        self.lock().data.range(prefix.to_string()..).take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    fn state_summary(&self) -> Arc<StateSummary> {
+        let mut inner = self.lock();
+        match &inner.summary {
+            Some(s) if s.tip == inner.tip.1 => s.clone(),
+            _ => {
+                let summary = Arc::new(StateSummary::build(&inner.tip.1, inner.accounts.clone(), inner.data.clone()));
+                inner.summary.insert(summary).clone()
+            }
+        }
+    }
+
+    fn save_mempool_tx(&self, tx: &Transaction, received_at: u64) {
+        self.lock().mempool.insert(tx.id.clone(), (tx.clone(), received_at));
+    }
+
+    fn remove_mempool_tx(&self, id: &str) {
+        self.lock().mempool.remove(id);
+    }
+
+    fn load_mempool(&self) -> Vec<(Transaction, u64)> {
+        self.lock().mempool.values().cloned().collect()
+    }
+}
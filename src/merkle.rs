@@ -0,0 +1,154 @@
+use crate::transaction::Transaction;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+// Tiền tố tách lá và nút trong để không thể giả một nút trong thành lá (second preimage)
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProofStep {
+    pub hash: String,
+    /// true nếu nút anh em nằm bên trái
+    pub left: bool,
+}
+
+/// Bằng chứng một giao dịch nằm trong block: băm lá rồi lần lượt ghép với `siblings` phải ra `root`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MerkleProof {
+    pub tx_id: String,
+    pub index: usize,
+    pub leaf: String,
+    pub siblings: Vec<ProofStep>,
+    pub root: String,
+}
+
+/// Hash lá = SHA256(0x00 || bincode(tx)), cam kết toàn bộ giao dịch kể cả chữ ký.
+pub fn leaf_hash(tx: &Transaction) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(bincode::serialize(tx).unwrap());
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+// Tầng kế tiếp: ghép từng cặp, nút lẻ cuối được đẩy thẳng lên (không nhân đôi)
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level.chunks(2).map(|pair| match pair {
+        [l, r] => node_hash(l, r),
+        [single] => *single,
+        _ => unreachable!(),
+    }).collect()
+}
+
+pub fn merkle_root(txs: &[Transaction]) -> String {
+    if txs.is_empty() {
+        return hex::encode(Sha256::digest([]));
+    }
+    let mut level: Vec<[u8; 32]> = txs.iter().map(leaf_hash).collect();
+    while level.len() > 1 { level = next_level(&level); }
+    hex::encode(level[0])
+}
+
+pub fn merkle_proof(txs: &[Transaction], tx_id: &str) -> Option<MerkleProof> {
+    let index = txs.iter().position(|t| t.id == tx_id)?;
+    let mut level: Vec<[u8; 32]> = txs.iter().map(leaf_hash).collect();
+    let leaf = hex::encode(level[index]);
+    let mut siblings = Vec::new();
+    let mut pos = index;
+    while level.len() > 1 {
+        let sibling = pos ^ 1;
+        if sibling < level.len() {
+            siblings.push(ProofStep { hash: hex::encode(level[sibling]), left: sibling < pos });
+        }
+        level = next_level(&level);
+        pos /= 2;
+    }
+    Some(MerkleProof { tx_id: tx_id.to_string(), index, leaf, siblings, root: hex::encode(level[0]) })
+}
+
+/// Light client gọi hàm này với giao dịch đầy đủ và `tx_root` lấy từ header block.
+pub fn verify_proof(tx: &Transaction, proof: &MerkleProof, tx_root: &str) -> bool {
+    let mut acc = leaf_hash(tx);
+    for step in &proof.siblings {
+        let sibling: [u8; 32] = match hex::decode(&step.hash).ok().and_then(|b| b.try_into().ok()) {
+            Some(h) => h,
+            None => return false,
+        };
+        acc = if step.left { node_hash(&sibling, &acc) } else { node_hash(&acc, &sibling) };
+    }
+    hex::encode(acc) == tx_root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn txs(n: u64) -> Vec<Transaction> {
+        (0..n).map(|i| Transaction::coinbase(1, &format!("PAPPAP{:032X}", i), i + 1, 0)).collect()
+    }
+
+    #[test]
+    fn every_tx_proves_against_root_for_odd_and_even_sizes() {
+        for n in 1..=9 {
+            let txs = txs(n);
+            let root = merkle_root(&txs);
+            for tx in &txs {
+                let proof = merkle_proof(&txs, &tx.id).unwrap();
+                assert_eq!(proof.root, root);
+                assert!(verify_proof(tx, &proof, &root), "n = {}, tx {}", n, proof.index);
+            }
+        }
+    }
+
+    #[test]
+    fn single_tx_root_is_its_leaf() {
+        let txs = txs(1);
+        let proof = merkle_proof(&txs, &txs[0].id).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert_eq!(proof.leaf, merkle_root(&txs));
+    }
+
+    #[test]
+    fn last_odd_leaf_is_promoted_without_duplication() {
+        // 3 lá: root = H(H(l0, l1), l2), không phải H(H(l0, l1), H(l2, l2))
+        let txs = txs(3);
+        let leaves: Vec<[u8; 32]> = txs.iter().map(leaf_hash).collect();
+        assert_eq!(merkle_root(&txs), hex::encode(node_hash(&node_hash(&leaves[0], &leaves[1]), &leaves[2])));
+        assert_eq!(merkle_proof(&txs, &txs[2].id).unwrap().siblings.len(), 1);
+    }
+
+    #[test]
+    fn tampered_proof_or_tx_is_rejected() {
+        let txs = txs(5);
+        let root = merkle_root(&txs);
+        let proof = merkle_proof(&txs, &txs[1].id).unwrap();
+        assert!(!verify_proof(&txs[2], &proof, &root));
+        assert!(!verify_proof(&txs[1], &proof, &merkle_root(&txs[..4])));
+
+        let mut flipped = proof.clone();
+        flipped.siblings[0].left = !flipped.siblings[0].left;
+        assert!(!verify_proof(&txs[1], &flipped, &root));
+
+        let mut truncated = proof.clone();
+        truncated.siblings.pop();
+        assert!(!verify_proof(&txs[1], &truncated, &root));
+
+        let mut garbage = proof;
+        garbage.siblings[0].hash = "zz".to_string();
+        assert!(!verify_proof(&txs[1], &garbage, &root));
+    }
+
+    #[test]
+    fn unknown_tx_has_no_proof() {
+        assert!(merkle_proof(&txs(4), "missing").is_none());
+        assert!(merkle_proof(&[], "missing").is_none());
+    }
+}
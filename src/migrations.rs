@@ -0,0 +1,133 @@
+use sled::Db;
+
+/// Phiên bản định dạng bản ghi hiện tại của DB (`meta:schema_version`).
+pub const SCHEMA_VERSION: u32 = 1;
+const VERSION_KEY: &str = "meta:schema_version";
+
+type Migration = fn(&Db) -> Result<(), String>;
+
+// MIGRATIONS[i] nâng DB từ v{i} lên v{i + 1}. Mỗi bước phải chạy lại được an toàn
+// vì số phiên bản chỉ được ghi sau khi bước đó xong.
+const MIGRATIONS: &[(&str, Migration)] = &[
+    ("lưu trữ chuỗi định dạng gốc", archive_legacy_chain),
+];
+
+pub fn schema_version(db: &Db) -> u32 {
+    db.get(VERSION_KEY).unwrap().map(|v| u32::from_be_bytes(v.as_ref().try_into().unwrap())).unwrap_or(0)
+}
+
+fn set_schema_version(db: &Db, version: u32) {
+    db.insert(VERSION_KEY, &version.to_be_bytes()).unwrap();
+    db.flush().unwrap();
+}
+
+/// Nâng DB lên `SCHEMA_VERSION` khi mở. DB trống được đánh dấu phiên bản hiện tại luôn;
+/// DB do bản mới hơn ghi thì từ chối mở.
+pub fn run(db: &Db) -> Result<(), String> {
+    if db.is_empty() {
+        set_schema_version(db, SCHEMA_VERSION);
+        return Ok(());
+    }
+    let current = schema_version(db);
+    if current > SCHEMA_VERSION {
+        return Err(format!("DB có schema v{}, mới hơn bản đang chạy (v{}): hãy nâng cấp node", current, SCHEMA_VERSION));
+    }
+    for (version, (name, migrate)) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        println!("💾 MIGRATION: v{} -> v{}: {}", version, version + 1, name);
+        migrate(db).map_err(|e| format!("Migration v{} -> v{} lỗi: {}", version, version + 1, e))?;
+        set_schema_version(db, version as u32 + 1);
+    }
+    Ok(())
+}
+
+// DB định dạng gốc (v0, chưa có chain spec): `block:{index}` của một chuỗi không có trạng thái, genesis khác chain spec
+// nên không thể nối tiếp hay nâng cấp thành chuỗi hợp lệ. Block cũ được giữ nguyên từng byte dưới `legacy:block:{index}`
+// và đỉnh cũ bị gỡ để node dựng genesis theo chain spec. Khóa node, thống kê và tri thức AI cục bộ (`ai:mem:`, vẫn được
+// `SledStore::recall_fact` đọc) không bị động tới.
+fn archive_legacy_chain(db: &Db) -> Result<(), String> {
+    let tip = db.get("chain:height").map_err(|e| e.to_string())?.and_then(|v| v.as_ref().try_into().ok()).map(u64::from_be_bytes);
+    let mut archived = 0;
+    for (key, value) in db.scan_prefix("block:").filter_map(|r| r.ok()) {
+        db.insert([b"legacy:".as_slice(), &key].concat(), value).map_err(|e| e.to_string())?;
+        db.remove(&key).map_err(|e| e.to_string())?;
+        archived += 1;
+    }
+    db.remove("chain:height").map_err(|e| e.to_string())?;
+    db.remove("chain:last_hash").map_err(|e| e.to_string())?;
+    db.flush().map_err(|e| e.to_string())?;
+    if archived > 0 {
+        println!("💾 MIGRATION: Lưu trữ {} block của chuỗi cũ (đỉnh #{}) dưới legacy:block:, chuỗi mới bắt đầu từ genesis của chain spec", archived, tip.unwrap_or(0));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{ChainStore, NodeStats, SledStore};
+
+    fn temp_db() -> Db {
+        sled::Config::new().temporary(true).open().unwrap()
+    }
+
+    // Bản ghi của DB định dạng gốc: chuỗi 2 block, đỉnh, thống kê và một tri thức AI cục bộ
+    fn write_baseline(db: &Db) {
+        db.insert("block:0", b"legacy-genesis".as_slice()).unwrap();
+        db.insert("block:1", b"legacy-block-1".as_slice()).unwrap();
+        db.insert("chain:height", &1u64.to_be_bytes()).unwrap();
+        db.insert("chain:last_hash", b"legacy-hash-1".as_slice()).unwrap();
+        db.insert("node:stats", bincode::serialize(&NodeStats { first_seen: 1, total_starts: 7, total_blocks: 2, reputation: 100 }).unwrap()).unwrap();
+        db.insert("ai:mem:xin chào", b"Chao ban".as_slice()).unwrap();
+    }
+
+    #[test]
+    fn empty_db_starts_at_current_schema() {
+        let db = temp_db();
+        run(&db).unwrap();
+        assert_eq!(schema_version(&db), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn baseline_chain_is_archived_and_local_data_kept() {
+        let db = temp_db();
+        write_baseline(&db);
+        run(&db).unwrap();
+        assert_eq!(schema_version(&db), SCHEMA_VERSION);
+        assert_eq!(db.scan_prefix("block:").count(), 0);
+        assert_eq!(db.get("legacy:block:1").unwrap().unwrap().as_ref(), b"legacy-block-1");
+        assert!(db.get("chain:height").unwrap().is_none() && db.get("chain:last_hash").unwrap().is_none());
+        assert_eq!(db.get("ai:mem:xin chào").unwrap().unwrap().as_ref(), b"Chao ban");
+        assert!(db.get("node:stats").unwrap().is_some());
+
+        // Chạy lại bước migration (vd. dừng trước khi ghi số phiên bản) không đổi gì thêm
+        archive_legacy_chain(&db).unwrap();
+        assert_eq!(db.scan_prefix("legacy:block:").count(), 2);
+    }
+
+    #[test]
+    fn newer_schema_is_refused_untouched() {
+        let db = temp_db();
+        write_baseline(&db);
+        set_schema_version(&db, SCHEMA_VERSION + 1);
+        assert!(run(&db).unwrap_err().contains("mới hơn"));
+        assert!(db.get("block:1").unwrap().is_some());
+    }
+
+    #[test]
+    fn baseline_db_opens_with_local_knowledge() {
+        let path = std::env::temp_dir().join(format!("pappap-migration-{}", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        {
+            let db = sled::Config::new().path(path).flush_every_ms(None).open().unwrap();
+            write_baseline(&db);
+            db.flush().unwrap();
+        }
+
+        let store = SledStore::new(path).unwrap();
+        assert_eq!((store.get_height(), store.get_block(0).is_none()), (0, true));
+        assert_eq!(store.load_stats().total_starts, 7);
+        assert_eq!(store.recall_fact("Xin Chào").as_deref(), Some("Chao ban"));
+        drop(store);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
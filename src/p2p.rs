@@ -1,5 +1,5 @@
 use libp2p::{
-    gossipsub, identity, noise, tcp, yamux,
+    gossipsub, identity, noise, tcp, yamux, request_response, StreamProtocol,
     swarm::{NetworkBehaviour, SwarmEvent},
     PeerId, Swarm, Transport, Multiaddr,
 };
@@ -9,24 +9,91 @@ use tokio::sync::mpsc;
 use std::error::Error;
 use std::str::FromStr;
 use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+use std::collections::HashMap;
+use sha2::{Sha256, Digest};
+use crate::sync::{SyncRequest, SyncResponse};
 
 #[derive(NetworkBehaviour)]
 struct PappapBehaviour {
     gossipsub: gossipsub::Behaviour,
     identify: libp2p::identify::Behaviour,
+    sync: request_response::json::Behaviour<SyncRequest, SyncResponse>,
+}
+
+// Số lần relay dữ liệu sai trước khi peer bị chặn
+const MAX_STRIKES: u32 = 5;
+
+/// Sự kiện mạng chuyển từ swarm sang chain.
+/// Message gossip chỉ được chuyển tiếp sau khi chain báo kết quả qua `P2PHandle::report`.
+pub enum NetEvent {
+    Block { data: Vec<u8>, from: PeerId, message_id: gossipsub::MessageId },
+    Transaction { data: Vec<u8>, from: PeerId, message_id: gossipsub::MessageId },
+    Vote { data: Vec<u8>, from: PeerId, message_id: gossipsub::MessageId },
+    PeerConnected(PeerId),
+    SyncRequest { request: SyncRequest, channel: request_response::ResponseChannel<SyncResponse> },
+    SyncResponse { peer: PeerId, response: SyncResponse },
+    SyncFailed { peer: PeerId },
+}
+
+enum P2PCommand {
+    PublishBlock(Vec<u8>),
+    PublishTx(Vec<u8>),
+    PublishVote(Vec<u8>),
+    Report { message_id: gossipsub::MessageId, from: PeerId, acceptance: gossipsub::MessageAcceptance },
+    Dial(String),
+    SendSync { peer: PeerId, request: SyncRequest },
+    RespondSync { channel: request_response::ResponseChannel<SyncResponse>, response: SyncResponse },
+}
+
+/// Handle gửi lệnh vào swarm mà không cần khóa `P2PNode` (swarm chạy trong vòng lặp riêng).
+#[derive(Clone)]
+pub struct P2PHandle {
+    cmd_tx: mpsc::UnboundedSender<P2PCommand>,
+}
+
+impl P2PHandle {
+    pub fn broadcast_block(&self, block_data: Vec<u8>) {
+        let _ = self.cmd_tx.send(P2PCommand::PublishBlock(block_data));
+    }
+    pub fn broadcast_tx(&self, tx_data: Vec<u8>) {
+        let _ = self.cmd_tx.send(P2PCommand::PublishTx(tx_data));
+    }
+    pub fn broadcast_vote(&self, vote_data: Vec<u8>) {
+        let _ = self.cmd_tx.send(P2PCommand::PublishVote(vote_data));
+    }
+    /// Kết quả kiểm tra message gossip: Accept => chuyển tiếp, Ignore => bỏ, Reject => bỏ và phạt peer.
+    pub fn report(&self, message_id: gossipsub::MessageId, from: PeerId, acceptance: gossipsub::MessageAcceptance) {
+        let _ = self.cmd_tx.send(P2PCommand::Report { message_id, from, acceptance });
+    }
+    pub fn dial(&self, addr: &str) {
+        let _ = self.cmd_tx.send(P2PCommand::Dial(addr.to_string()));
+    }
+    pub fn send_sync_request(&self, peer: PeerId, request: SyncRequest) {
+        let _ = self.cmd_tx.send(P2PCommand::SendSync { peer, request });
+    }
+    pub fn respond_sync(&self, channel: request_response::ResponseChannel<SyncResponse>, response: SyncResponse) {
+        let _ = self.cmd_tx.send(P2PCommand::RespondSync { channel, response });
+    }
 }
 
 pub struct P2PNode {
     swarm: Swarm<PappapBehaviour>,
     topic: gossipsub::IdentTopic,
+    tx_topic: gossipsub::IdentTopic,
+    vote_topic: gossipsub::IdentTopic,
+    strikes: HashMap<PeerId, u32>,
     pub peer_count: Arc<AtomicUsize>,
+    event_tx: mpsc::UnboundedSender<NetEvent>,
+    cmd_tx: mpsc::UnboundedSender<P2PCommand>,
+    cmd_rx: mpsc::UnboundedReceiver<P2PCommand>,
 }
 
 impl P2PNode {
     // S?A L?I: Hàm new nh?n thêm peer_count
-    pub async fn new(local_key: identity::Keypair, peer_count: Arc<AtomicUsize>) -> Result<(Self, mpsc::UnboundedReceiver<Vec<u8>>, PeerId), Box<dyn Error>> {
+    /// `listen`: multiaddr lắng nghe, vd. `/ip4/0.0.0.0/tcp/9000`.
+    pub async fn new(local_key: identity::Keypair, peer_count: Arc<AtomicUsize>, listen: &str) -> Result<(Self, mpsc::UnboundedReceiver<NetEvent>, PeerId), Box<dyn Error>> {
         let local_peer_id = PeerId::from(local_key.public());
-        
+
         let _transport = tcp::tokio::Transport::default()
             .upgrade(libp2p::core::upgrade::Version::V1Lazy)
             .authenticate(noise::Config::new(&local_key)?)
@@ -34,9 +101,18 @@ impl P2PNode {
             .boxed();
 
         let topic = gossipsub::IdentTopic::new("pappap-mainnet-blocks");
+        let tx_topic = gossipsub::IdentTopic::new("pappap-mainnet-txs");
+        let vote_topic = gossipsub::IdentTopic::new("pappap-mainnet-votes");
+        // Message id = SHA256 nội dung: cùng một block/giao dịch từ nhiều nguồn chỉ xử lý một lần.
+        // Id được trao đổi giữa các peer (IHAVE/IWANT) nên phải ổn định giữa các bản build
+        let message_id_fn = |message: &gossipsub::Message| {
+            gossipsub::MessageId::from(hex::encode(Sha256::digest(&message.data)))
+        };
         let gossip_config = gossipsub::ConfigBuilder::default()
             .heartbeat_interval(Duration::from_secs(1))
             .validation_mode(gossipsub::ValidationMode::Strict)
+            .validate_messages()
+            .message_id_fn(message_id_fn)
             .build()
             .map_err(|e| format!("Config error: {}", e)).unwrap();
 
@@ -48,9 +124,15 @@ impl P2PNode {
             identify: libp2p::identify::Behaviour::new(
                 libp2p::identify::Config::new("pappap/1.0.0".to_string(), local_key.public())
             ),
+            sync: request_response::json::Behaviour::new(
+                [(StreamProtocol::new("/pappap/sync/1"), request_response::ProtocolSupport::Full)],
+                request_response::Config::default().with_request_timeout(Duration::from_secs(30)),
+            ),
         };
 
         behaviour.gossipsub.subscribe(&topic).unwrap();
+        behaviour.gossipsub.subscribe(&tx_topic).unwrap();
+        behaviour.gossipsub.subscribe(&vote_topic).unwrap();
 
         let mut swarm = libp2p::SwarmBuilder::with_existing_identity(local_key.clone())
             .with_tokio()
@@ -59,28 +141,86 @@ impl P2PNode {
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
 
-        swarm.listen_on("/ip4/0.0.0.0/tcp/9000".parse()?)?;
+        swarm.listen_on(listen.parse()?)?;
+
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        Ok((Self { swarm, topic, tx_topic, vote_topic, strikes: HashMap::new(), peer_count, event_tx, cmd_tx, cmd_rx }, event_rx, local_peer_id))
+    }
 
-        let (_, rx) = mpsc::unbounded_channel();
-        Ok((Self { swarm, topic, peer_count }, rx, local_peer_id))
+    pub fn handle(&self) -> P2PHandle {
+        P2PHandle { cmd_tx: self.cmd_tx.clone() }
     }
 
-    pub fn dial(&mut self, addr: &str) {
+    fn dial(&mut self, addr: &str) {
         if let Ok(ma) = Multiaddr::from_str(addr) {
             if let Err(e) = self.swarm.dial(ma) { println!("? Dial failed: {:?}", e); }
         }
     }
-    pub fn broadcast_block(&mut self, block_data: Vec<u8>) {
+    fn broadcast_block(&mut self, block_data: Vec<u8>) {
         let _ = self.swarm.behaviour_mut().gossipsub.publish(self.topic.clone(), block_data);
     }
+    fn report(&mut self, message_id: gossipsub::MessageId, from: PeerId, acceptance: gossipsub::MessageAcceptance) {
+        let rejected = matches!(acceptance, gossipsub::MessageAcceptance::Reject);
+        let _ = self.swarm.behaviour_mut().gossipsub.report_message_validation_result(&message_id, &from, acceptance);
+        if rejected {
+            let strikes = self.strikes.entry(from).or_insert(0);
+            *strikes += 1;
+            if *strikes >= MAX_STRIKES {
+                println!("?? P2P: Chặn peer {} (relay dữ liệu sai {} lần)", from, strikes);
+                self.swarm.behaviour_mut().gossipsub.blacklist_peer(&from);
+                let _ = self.swarm.disconnect_peer_id(from);
+            }
+        }
+    }
     pub async fn run(&mut self) {
         loop {
-            match self.swarm.select_next_some().await {
-                SwarmEvent::NewListenAddr { address, .. } => println!("?? P2P: {}", address),
-                SwarmEvent::ConnectionEstablished { .. } => { self.peer_count.fetch_add(1, Ordering::Relaxed); },
-                SwarmEvent::ConnectionClosed { .. } => { self.peer_count.fetch_sub(1, Ordering::Relaxed); },
-                _ => {}
+            tokio::select! {
+                event = self.swarm.select_next_some() => match event {
+                    SwarmEvent::NewListenAddr { address, .. } => println!("?? P2P: {}", address),
+                    SwarmEvent::ConnectionEstablished { peer_id, num_established, .. } => {
+                        self.peer_count.fetch_add(1, Ordering::Relaxed);
+                        if num_established.get() == 1 { let _ = self.event_tx.send(NetEvent::PeerConnected(peer_id)); }
+                    },
+                    SwarmEvent::ConnectionClosed { .. } => { self.peer_count.fetch_sub(1, Ordering::Relaxed); },
+                    SwarmEvent::Behaviour(PappapBehaviourEvent::Gossipsub(gossipsub::Event::Message { propagation_source, message_id, message })) => {
+                        let from = propagation_source;
+                        if message.topic == self.topic.hash() {
+                            let _ = self.event_tx.send(NetEvent::Block { data: message.data, from, message_id });
+                        } else if message.topic == self.tx_topic.hash() {
+                            let _ = self.event_tx.send(NetEvent::Transaction { data: message.data, from, message_id });
+                        } else if message.topic == self.vote_topic.hash() {
+                            let _ = self.event_tx.send(NetEvent::Vote { data: message.data, from, message_id });
+                        }
+                    }
+                    SwarmEvent::Behaviour(PappapBehaviourEvent::Sync(event)) => self.on_sync_event(event),
+                    _ => {}
+                },
+                Some(cmd) = self.cmd_rx.recv() => match cmd {
+                    P2PCommand::PublishBlock(data) => self.broadcast_block(data),
+                    P2PCommand::PublishTx(data) => { let _ = self.swarm.behaviour_mut().gossipsub.publish(self.tx_topic.clone(), data); },
+                    P2PCommand::PublishVote(data) => { let _ = self.swarm.behaviour_mut().gossipsub.publish(self.vote_topic.clone(), data); },
+                    P2PCommand::Report { message_id, from, acceptance } => self.report(message_id, from, acceptance),
+                    P2PCommand::Dial(addr) => self.dial(&addr),
+                    P2PCommand::SendSync { peer, request } => { self.swarm.behaviour_mut().sync.send_request(&peer, request); },
+                    P2PCommand::RespondSync { channel, response } => { let _ = self.swarm.behaviour_mut().sync.send_response(channel, response); },
+                },
             }
         }
     }
+
+    fn on_sync_event(&mut self, event: request_response::Event<SyncRequest, SyncResponse>) {
+        let net_event = match event {
+            request_response::Event::Message { message: request_response::Message::Request { request, channel, .. }, .. } =>
+                NetEvent::SyncRequest { request, channel },
+            request_response::Event::Message { peer, message: request_response::Message::Response { response, .. } } =>
+                NetEvent::SyncResponse { peer, response },
+            request_response::Event::OutboundFailure { peer, error, .. } => {
+                println!("? Sync request tới {} lỗi: {:?}", peer, error);
+                NetEvent::SyncFailed { peer }
+            }
+            _ => return,
+        };
+        let _ = self.event_tx.send(net_event);
+    }
 }
@@ -0,0 +1,165 @@
+use crate::genesis::ChainSpec;
+use crate::state::StateView;
+use crate::storage::ChainStore;
+use crate::transaction::MIN_FEE;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+
+pub const PARAMS_KEY: &str = "params:chain";
+const EMITTED_KEY: &str = "supply:emitted";
+pub const DEFAULT_MAX_BLOCK_TXS: u32 = 50;
+
+/// Cách đánh số slot: slot `anchor_slot` bắt đầu ở `anchor_ms`, mỗi slot dài `interval_ms`.
+/// Đổi block interval chỉ dời mốc sang slot kế tiếp nên số slot vẫn tăng liên tục.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SlotTiming {
+    pub anchor_slot: u64,
+    pub anchor_ms: i64,
+    pub interval_ms: u64,
+}
+
+impl SlotTiming {
+    pub fn slot_at(&self, now_ms: i64) -> u64 {
+        // Mốc mới nằm ở đầu slot kế tiếp của block kích hoạt: trước mốc vẫn là slot ngay trước đó
+        if now_ms < self.anchor_ms { return self.anchor_slot.saturating_sub(1); }
+        self.anchor_slot + (now_ms - self.anchor_ms) as u64 / self.interval_ms
+    }
+
+    pub fn slot_start_ms(&self, slot: u64) -> i64 {
+        self.anchor_ms + (slot.saturating_sub(self.anchor_slot) * self.interval_ms) as i64
+    }
+}
+
+/// Thay đổi tham số chuỗi mà một đề xuất NeuroDAO có thể mang theo.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ParamChange {
+    BlockIntervalMs(u64),
+    MaxBlockTxs(u32),
+    MinFee(u64),
+    /// Phần trăm phần thưởng theo lịch phát hành được trả (0-100).
+    RewardPercent(u64),
+    BlacklistAdd(String),
+    BlacklistRemove(String),
+}
+
+impl ParamChange {
+    pub fn canonical(&self) -> String {
+        match self {
+            ParamChange::BlockIntervalMs(v) => format!("block_interval_ms={}", v),
+            ParamChange::MaxBlockTxs(v) => format!("max_block_txs={}", v),
+            ParamChange::MinFee(v) => format!("min_fee={}", v),
+            ParamChange::RewardPercent(v) => format!("reward_percent={}", v),
+            ParamChange::BlacklistAdd(w) => format!("blacklist_add={}", hex::encode(Sha256::digest(w.as_bytes()))),
+            ParamChange::BlacklistRemove(w) => format!("blacklist_remove={}", hex::encode(Sha256::digest(w.as_bytes()))),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ParamChange::BlockIntervalMs(v) if !(100..=60_000).contains(v) => Err("Block interval phải trong 100-60000 ms".to_string()),
+            ParamChange::MaxBlockTxs(v) if !(1..=10_000).contains(v) => Err("Số giao dịch mỗi block phải trong 1-10000".to_string()),
+            ParamChange::MinFee(v) if *v == 0 => Err("Phí tối thiểu phải lớn hơn 0".to_string()),
+            ParamChange::RewardPercent(v) if *v > 100 => Err("Tỷ lệ phần thưởng tối đa 100%".to_string()),
+            ParamChange::BlacklistAdd(w) | ParamChange::BlacklistRemove(w)
+                if w.trim().is_empty() || w.chars().count() > 64 || *w != w.to_lowercase() =>
+            {
+                Err("Từ khóa cấm phải viết thường, 1-64 ký tự".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Tham số chuỗi có thể đổi qua governance, nằm trong state (`params:chain`) nên mọi node áp giống nhau.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChainParams {
+    pub timing: SlotTiming,
+    pub max_block_txs: u32,
+    pub min_fee: u64,
+    pub reward_percent: u64,
+    /// Từ khóa cấm bổ sung ngoài danh sách gốc của `EthicsFilter`.
+    pub ethics_blacklist: Vec<String>,
+}
+
+impl ChainParams {
+    pub fn initial(spec: &ChainSpec) -> Self {
+        Self {
+            timing: SlotTiming { anchor_slot: 0, anchor_ms: spec.genesis_timestamp * 1000, interval_ms: spec.block_interval_ms.max(1) },
+            max_block_txs: DEFAULT_MAX_BLOCK_TXS,
+            min_fee: MIN_FEE,
+            reward_percent: 100,
+            ethics_blacklist: Vec::new(),
+        }
+    }
+
+    /// Tham số hiện hành (genesis luôn ghi sẵn nên khóa này không bao giờ thiếu trên chuỗi hợp lệ).
+    pub fn load(view: &StateView) -> Result<Self, String> {
+        Self::decode(view.get_data(PARAMS_KEY))
+    }
+
+    /// Tham số sau block đỉnh đã lưu.
+    pub fn current(storage: &dyn ChainStore) -> Result<Self, String> {
+        Self::decode(storage.get_state_data(PARAMS_KEY))
+    }
+
+    /// Kiểm lúc mở DB rằng tham số và tổng phần thưởng đã trả đọc được.
+    pub fn check_stored(storage: &dyn ChainStore) -> Result<(), String> {
+        if storage.get_state_data(PARAMS_KEY).is_some() { Self::current(storage)?; }
+        emitted(storage).map(|_| ())
+    }
+
+    fn decode(raw: Option<Vec<u8>>) -> Result<Self, String> {
+        let raw = raw.ok_or(format!("State thiếu {}", PARAMS_KEY))?;
+        bincode::deserialize(&raw).map_err(|e| format!("{} hỏng: {}", PARAMS_KEY, e))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).unwrap()
+    }
+
+    pub fn save(&self, view: &mut StateView) {
+        view.set_data(PARAMS_KEY, Some(self.encode()));
+    }
+
+    /// Áp thay đổi trong block ở `slot`; interval mới có hiệu lực từ slot kế tiếp.
+    pub fn apply(&mut self, change: &ParamChange, slot: u64) {
+        match change {
+            ParamChange::BlockIntervalMs(v) => {
+                let next = slot + 1;
+                self.timing = SlotTiming { anchor_slot: next, anchor_ms: self.timing.slot_start_ms(next), interval_ms: *v };
+            }
+            ParamChange::MaxBlockTxs(v) => self.max_block_txs = *v,
+            ParamChange::MinFee(v) => self.min_fee = *v,
+            ParamChange::RewardPercent(v) => self.reward_percent = *v,
+            ParamChange::BlacklistAdd(w) => {
+                if !self.ethics_blacklist.contains(w) { self.ethics_blacklist.push(w.clone()); }
+            }
+            ParamChange::BlacklistRemove(w) => self.ethics_blacklist.retain(|x| x != w),
+        }
+    }
+
+    /// Phần thưởng thực trả của block: lịch phát hành trong chain spec nhân `reward_percent`.
+    pub fn block_reward(&self, spec: &ChainSpec, height: u64) -> u64 {
+        (spec.block_reward(height) as u128 * self.reward_percent as u128 / 100) as u64
+    }
+}
+
+/// Tổng phần thưởng đã rút khỏi quỹ từ genesis tới block đỉnh đã lưu.
+pub fn emitted(storage: &dyn ChainStore) -> Result<u128, String> {
+    decode_emitted(storage.get_state_data(EMITTED_KEY))
+}
+
+fn decode_emitted(raw: Option<Vec<u8>>) -> Result<u128, String> {
+    match raw {
+        Some(v) => v.try_into().map(u128::from_be_bytes).map_err(|_| format!("{} hỏng", EMITTED_KEY)),
+        None => Ok(0),
+    }
+}
+
+/// Cộng phần thưởng thực trả của block vào tổng đã phát (nằm trong state nên reorg hoàn tác được).
+pub fn add_emitted(view: &mut StateView, amount: u64) -> Result<(), String> {
+    if amount == 0 { return Ok(()); }
+    let total = decode_emitted(view.get_data(EMITTED_KEY))? + amount as u128;
+    view.set_data(EMITTED_KEY, Some(total.to_be_bytes().to_vec()));
+    Ok(())
+}
@@ -18,16 +18,21 @@ pub struct QuantumWallet {
 }
 
 impl QuantumWallet {
-    pub fn new() -> Self {
+    /// Sinh cặp khóa Dilithium5 mới (public, secret); chỉ gọi khi node chưa có keystore.
+    pub fn generate_keypair() -> (Vec<u8>, Vec<u8>) {
         println!("🛡️  INITIATING QUANTUM SHIELD (Dilithium5)...");
         let (pk, sk) = keypair();
         
         println!("⚛️  QUANTUM KEYS GENERATED | Size: PK={}B, SK={}B", 
             pk.as_bytes().len(), sk.as_bytes().len());
 
+        (pk.as_bytes().to_vec(), sk.as_bytes().to_vec())
+    }
+
+    pub fn from_keys(public_key: Vec<u8>, secret_key: Vec<u8>) -> Self {
         Self {
-            public_key: pk.as_bytes().to_vec(),
-            secret_key: Arc::new(RwLock::new(sk.as_bytes().to_vec())),
+            public_key,
+            secret_key: Arc::new(RwLock::new(secret_key)),
         }
     }
 
@@ -0,0 +1,330 @@
+use crate::block::Block;
+use crate::finality::QuorumCertificate;
+use crate::genesis::ChainSpec;
+use crate::state::{Account, compute_root};
+use crate::storage::ChainStore;
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+
+const SNAPSHOT_VERSION: u8 = 2;
+// Giữ tối thiểu chừng này block khi prune để reorg nông vẫn hoàn tác được
+const MIN_KEEP_BLOCKS: u64 = 100;
+// Prune theo lô để không phải quét mỗi block
+const PRUNE_EVERY: u64 = 100;
+// Số file snapshot định kỳ giữ lại
+const KEEP_SNAPSHOTS: usize = 2;
+
+/// Ảnh chụp trạng thái tại một block chuỗi chính: đủ để node mới chạy tiếp mà không cần tải lại lịch sử.
+/// Tài khoản + dữ liệu module (kể cả tri thức AI) được ràng buộc bởi `block.state_root`.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u8,
+    pub chain_id: String,
+    pub genesis_hash: String,
+    pub block: Block,
+    pub total_work: f64,
+    pub accounts: BTreeMap<String, Account>,
+    pub data: BTreeMap<String, Vec<u8>>,
+    /// Chứng chỉ finality của `block` (không có khi chain spec chưa khai báo validator).
+    pub qc: Option<QuorumCertificate>,
+}
+
+impl Snapshot {
+    /// Chụp tại block đã chốt gần nhất để snapshot mang theo QC của nó và tự kiểm tra được;
+    /// chain spec chưa có validator (không có finality) thì chụp tại đỉnh.
+    pub fn capture(storage: &dyn ChainStore, spec: &ChainSpec) -> Result<Self, String> {
+        if spec.validators.is_empty() { return Self::capture_at(storage, spec, storage.get_height()); }
+        match storage.get_finalized().0 {
+            0 => Err("Chưa có block nào được chốt để chụp snapshot".to_string()),
+            height => Self::capture_at(storage, spec, height),
+        }
+    }
+
+    // Trạng thái đã lưu là của đỉnh: lùi về `height` bằng bản hoàn tác của các block phía trên
+    fn capture_at(storage: &dyn ChainStore, spec: &ChainSpec, height: u64) -> Result<Self, String> {
+        let block = storage.get_block(height).ok_or(format!("Thiếu block #{}", height))?;
+        let mut accounts: BTreeMap<String, Account> = storage.load_accounts().into_iter().collect();
+        let mut data: BTreeMap<String, Vec<u8>> = storage.scan_state_data("").into_iter().collect();
+        for i in ((height + 1)..=storage.get_height()).rev() {
+            let undo = storage.get_block(i).and_then(|b| storage.get_undo(&b.hash)).ok_or(format!("Thiếu bản hoàn tác của block #{}", i))?;
+            for (addr, acct) in undo.accounts {
+                match acct {
+                    Some(a) => { accounts.insert(addr, a); }
+                    None => { accounts.remove(&addr); }
+                }
+            }
+            for (key, value) in undo.data {
+                match value {
+                    Some(v) => { data.insert(key, v); }
+                    None => { data.remove(&key); }
+                }
+            }
+        }
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            chain_id: spec.chain_id.clone(),
+            genesis_hash: spec.genesis_block().hash,
+            total_work: storage.get_total_work(&block.hash).unwrap_or(0.0),
+            accounts,
+            data,
+            qc: storage.get_qc(height),
+            block,
+        })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        // Ghi ra file tạm rồi đổi tên để không bao giờ để lại snapshot dở dang
+        let tmp = format!("{}.tmp", path);
+        std::fs::write(&tmp, bincode::serialize(self).unwrap()).map_err(|e| format!("Không ghi được '{}': {}", tmp, e))?;
+        std::fs::rename(&tmp, path).map_err(|e| format!("Không ghi được '{}': {}", path, e))
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let raw = std::fs::read(path).map_err(|e| format!("Không đọc được '{}': {}", path, e))?;
+        let snap: Self = bincode::deserialize(&raw).map_err(|e| format!("Snapshot '{}' hỏng: {}", path, e))?;
+        if snap.version != SNAPSHOT_VERSION {
+            return Err(format!("Snapshot phiên bản {} không được hỗ trợ", snap.version));
+        }
+        Ok(snap)
+    }
+
+    /// Kiểm tra snapshot thuộc chuỗi này, trạng thái khớp state_root của block, và block là block đã chốt:
+    /// hoặc trùng `trusted_hash` (lấy từ nguồn tin cậy, vd. /api/block của node khác), hoặc có QC hợp lệ theo chain spec.
+    pub fn verify(&self, spec: &ChainSpec, trusted_hash: Option<&str>) -> Result<(), String> {
+        if self.chain_id != spec.chain_id || self.genesis_hash != spec.genesis_block().hash {
+            return Err(format!("Snapshot thuộc chuỗi khác ({} / genesis {})", self.chain_id, self.genesis_hash));
+        }
+        if self.block.index == 0 {
+            return Err("Snapshot tại genesis không cần nạp".to_string());
+        }
+        self.block.verify_seal()?;
+        if compute_root(&self.accounts, &self.data) != self.block.state_root {
+            return Err(format!("Trạng thái trong snapshot không khớp state_root của block #{}", self.block.index));
+        }
+        match (trusted_hash, &self.qc) {
+            (Some(hash), _) if hash != self.block.hash => {
+                Err(format!("Block #{} của snapshot ({}) khác hash tin cậy {}", self.block.index, self.block.hash, hash))
+            }
+            (Some(_), _) => Ok(()),
+            (None, Some(qc)) if qc.height == self.block.index && qc.block_hash == self.block.hash => qc.verify(spec),
+            (None, _) => Err("Snapshot không có QC của block, cần --hash <hash block đã chốt> từ nguồn tin cậy".to_string()),
+        }
+    }
+}
+
+/// Chế độ lưu trữ: PAPPAP_PRUNE_KEEP_BLOCKS (0 = giữ toàn bộ lịch sử) và PAPPAP_SNAPSHOT_INTERVAL
+/// (chụp snapshot vào PAPPAP_SNAPSHOT_DIR mỗi bấy nhiêu block, 0 = tắt).
+#[derive(Clone, Debug)]
+pub struct PruneConfig {
+    pub keep_blocks: u64,
+    pub snapshot_interval: u64,
+    pub snapshot_dir: String,
+}
+
+impl PruneConfig {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        let mut keep_blocks = var("PAPPAP_PRUNE_KEEP_BLOCKS");
+        if keep_blocks > 0 && keep_blocks < MIN_KEEP_BLOCKS {
+            println!("⚠️  PRUNE: Giữ tối thiểu {} block", MIN_KEEP_BLOCKS);
+            keep_blocks = MIN_KEEP_BLOCKS;
+        }
+        Self {
+            keep_blocks,
+            snapshot_interval: var("PAPPAP_SNAPSHOT_INTERVAL"),
+            snapshot_dir: std::env::var("PAPPAP_SNAPSHOT_DIR").unwrap_or_else(|_| "snapshots".to_string()),
+        }
+    }
+
+    /// Gọi ngay sau khi block `height` vào chuỗi chính (đang giữ tip_lock nên trạng thái chụp được là nhất quán).
+    pub fn on_commit(&self, storage: &dyn ChainStore, spec: &ChainSpec, height: u64) {
+        if self.snapshot_interval > 0 && height.is_multiple_of(self.snapshot_interval) {
+            match self.write_periodic(storage, spec) {
+                Ok(Some((at, path))) => println!("\n📸 SNAPSHOT: Block #{} -> {}", at, path),
+                Ok(None) => {}
+                Err(e) => println!("\n⚠️  SNAPSHOT: {}", e),
+            }
+        }
+        if self.keep_blocks > 0 && height.is_multiple_of(PRUNE_EVERY) {
+            // Có finality thì không prune qua block đã chốt: snapshot cần bản hoàn tác từ đó tới đỉnh
+            let mut keep_from = height.saturating_sub(self.keep_blocks);
+            if !spec.validators.is_empty() { keep_from = keep_from.min(storage.get_finalized().0); }
+            let pruned = storage.prune_below(keep_from);
+            if pruned > 0 { println!("\n✂️  PRUNE: Xóa {} block cũ, giữ từ #{}", pruned, storage.get_pruned_height() + 1); }
+        }
+    }
+
+    // Trả về (height, file) của snapshot mới; None nếu block đã chốt chưa đổi từ lần chụp trước
+    fn write_periodic(&self, storage: &dyn ChainStore, spec: &ChainSpec) -> Result<Option<(u64, String)>, String> {
+        let snap = Snapshot::capture(storage, spec)?;
+        let path = format!("{}/snapshot-{:012}.bin", self.snapshot_dir, snap.block.index);
+        if std::path::Path::new(&path).exists() { return Ok(None); }
+        std::fs::create_dir_all(&self.snapshot_dir).map_err(|e| e.to_string())?;
+        snap.save(&path)?;
+        let mut old: Vec<_> = std::fs::read_dir(&self.snapshot_dir).map_err(|e| e.to_string())?
+            .filter_map(|e| e.ok()).map(|e| e.path())
+            .filter(|p| p.extension().map(|x| x == "bin").unwrap_or(false))
+            .collect();
+        old.sort();
+        let excess = old.len().saturating_sub(KEEP_SNAPSHOTS);
+        for p in &old[..excess] { let _ = std::fs::remove_file(p); }
+        Ok(Some((snap.block.index, path)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finality::{Vote, VoteKind};
+    use crate::genesis::{EmissionSpec, ValidatorSpec};
+    use crate::governance::GovernanceParams;
+    use crate::keystore::NodeKeys;
+    use crate::memstore::MemoryStore;
+    use crate::quantum::QuantumWallet;
+    use crate::snn_core::neural_work;
+    use crate::state::StateDiff;
+    use crate::storage::SledStore;
+
+    const USER: &str = "PAPPAP00000000000000000000000000000001";
+
+    fn spec(validator: &QuantumWallet) -> ChainSpec {
+        ChainSpec {
+            chain_id: "pappap-test".to_string(),
+            genesis_timestamp: 0,
+            block_interval_ms: 800,
+            validators: vec![ValidatorSpec { name: "A".to_string(), pqc_pubkey: hex::encode(&validator.public_key), stake: 1 }],
+            balances: BTreeMap::from([("COMMUNITY_POOL".to_string(), 1_000_000), (USER.to_string(), 1_000)]),
+            emission: EmissionSpec::default(),
+            governance: GovernanceParams::default(),
+        }
+    }
+
+    fn block(index: u64, prev: &Block, state_root: String, miner: &QuantumWallet) -> Block {
+        let pubkey = hex::encode(&miner.public_key);
+        let spike = neural_work(&prev.hash, &pubkey, index);
+        Block::new(index, index, prev.hash.clone(), state_root, Vec::new(), spike, "A".to_string(), pubkey)
+    }
+
+    // Chuỗi chính #0..=#height do `miner` ký; block #i đặt số dư USER = 1000 + i và ghi `test:{i}`
+    async fn build(storage: &dyn ChainStore, spec: &ChainSpec, miner: &QuantumWallet, height: u64) -> Vec<Block> {
+        let mut blocks = vec![spec.genesis_block()];
+        storage.init_genesis(&blocks[0], &spec.genesis_accounts(), &spec.genesis_data());
+        for i in 1..=height {
+            let before = storage.get_account(USER).unwrap();
+            let after = Account { balance: 1_000 + i, ..before.clone() };
+            let key = format!("test:{}", i);
+            let mut accounts: BTreeMap<String, Account> = storage.load_accounts().into_iter().collect();
+            let mut data: BTreeMap<String, Vec<u8>> = storage.scan_state_data("").into_iter().collect();
+            accounts.insert(USER.to_string(), after.clone());
+            data.insert(key.clone(), vec![i as u8]);
+            let mut b = block(i, &blocks[i as usize - 1], compute_root(&accounts, &data), miner);
+            b.pqc_signature = hex::encode(miner.sign_data(b.hash.as_bytes()).await);
+            let diff = StateDiff {
+                after: BTreeMap::from([(USER.to_string(), after)]),
+                before: BTreeMap::from([(USER.to_string(), Some(before))]),
+                data_after: BTreeMap::from([(key.clone(), Some(vec![i as u8]))]),
+                data_before: BTreeMap::from([(key, None)]),
+            };
+            storage.store_block(&b, i as f64);
+            storage.commit_block(&b, &diff);
+            blocks.push(b);
+        }
+        blocks
+    }
+
+    async fn finalize(storage: &dyn ChainStore, spec: &ChainSpec, validator: &QuantumWallet, block: &Block) {
+        let vote = Vote::sign(validator, VoteKind::Precommit, block.index, &block.hash, &spec.chain_id).await;
+        storage.set_finalized(&QuorumCertificate { height: block.index, block_hash: block.hash.clone(), votes: vec![vote] });
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pappap-{}-{}", name, uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn snapshot_at_finalized_block_verifies_and_restores() {
+        let validator = NodeKeys::generate(None).wallet();
+        let spec = spec(&validator);
+        let source = MemoryStore::new();
+        let blocks = build(&source, &spec, &validator, 4).await;
+        assert!(Snapshot::capture(&source, &spec).is_err_and(|e| e.contains("chốt")));
+
+        // Đỉnh ở #4 nhưng chỉ #2 đã chốt: snapshot lùi trạng thái về #2 và mang QC của #2
+        finalize(&source, &spec, &validator, &blocks[2]).await;
+        let snap = Snapshot::capture(&source, &spec).unwrap();
+        assert_eq!(snap.block.hash, blocks[2].hash);
+        assert_eq!(snap.accounts[USER].balance, 1_002);
+        assert!(snap.data.contains_key("test:2") && !snap.data.contains_key("test:3"));
+        snap.verify(&spec, None).unwrap();
+
+        let path = temp_path("snapshot.bin");
+        snap.save(path.to_str().unwrap()).unwrap();
+        let loaded = Snapshot::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let db = temp_path("restore");
+        let targets: [Box<dyn ChainStore>; 2] = [Box::new(MemoryStore::new()), Box::new(SledStore::new(db.to_str().unwrap()).unwrap())];
+        for target in &targets {
+            target.restore_snapshot(&spec.genesis_block(), &loaded).unwrap();
+            assert_eq!((target.get_height(), target.get_last_hash()), (2, blocks[2].hash.clone()));
+            assert_eq!(target.state_root(), blocks[2].state_root);
+            assert_eq!((target.get_finalized().0, target.get_pruned_height()), (2, 1));
+            assert!(target.get_qc(2).is_some() && target.check_consistency().is_ok());
+            assert!(target.restore_snapshot(&spec.genesis_block(), &loaded).is_err());
+        }
+        drop(targets);
+        std::fs::remove_dir_all(db).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tampered_or_unconfirmed_snapshot_is_rejected() {
+        let validator = NodeKeys::generate(None).wallet();
+        let spec = spec(&validator);
+        let storage = MemoryStore::new();
+        let blocks = build(&storage, &spec, &validator, 2).await;
+        finalize(&storage, &spec, &validator, &blocks[2]).await;
+
+        let mut snap = Snapshot::capture(&storage, &spec).unwrap();
+        snap.accounts.get_mut(USER).unwrap().balance += 1;
+        assert!(snap.verify(&spec, None).unwrap_err().contains("state_root"));
+
+        let mut snap = Snapshot::capture(&storage, &spec).unwrap();
+        assert!(snap.verify(&spec, Some(&blocks[1].hash)).is_err());
+        snap.qc = None;
+        assert!(snap.verify(&spec, None).unwrap_err().contains("--hash"));
+        snap.verify(&spec, Some(&blocks[2].hash)).unwrap();
+
+        // QC của validator ngoài chain spec không đủ quorum
+        let outsider = NodeKeys::generate(None).wallet();
+        finalize(&storage, &spec, &outsider, &blocks[2]).await;
+        assert!(Snapshot::capture(&storage, &spec).unwrap().verify(&spec, None).unwrap_err().contains("quorum"));
+        let mut other = spec.clone();
+        other.chain_id = "pappap-other".to_string();
+        assert!(Snapshot::capture(&storage, &spec).unwrap().verify(&other, None).unwrap_err().contains("chuỗi khác"));
+    }
+
+    #[tokio::test]
+    async fn prune_drops_side_branches_below_pruned_height() {
+        let validator = NodeKeys::generate(None).wallet();
+        let spec = spec(&validator);
+        let path = temp_path("prune");
+        let stores: [Box<dyn ChainStore>; 2] = [Box::new(MemoryStore::new()), Box::new(SledStore::new(path.to_str().unwrap()).unwrap())];
+        for storage in &stores {
+            let main = build(&**storage, &spec, &validator, 6).await;
+            // Nhánh phụ rẽ từ #1 (#2', #3') và từ #4 (#5')
+            let side2 = block(2, &main[1], String::new(), &validator);
+            let side3 = block(3, &side2, String::new(), &validator);
+            let side5 = block(5, &main[4], String::new(), &validator);
+            for b in [&side2, &side3, &side5] { storage.store_block(b, 0.0); }
+
+            assert_eq!(storage.prune_below(4), 3);
+            assert_eq!(storage.get_pruned_height(), 3);
+            for b in [&side2, &side3, &main[1], &main[3]] {
+                assert!(storage.get_block_by_hash(&b.hash).is_none() && storage.get_total_work(&b.hash).is_none());
+            }
+            for b in [&side5, &main[0], &main[4], &main[6]] { assert!(storage.get_block_by_hash(&b.hash).is_some()); }
+            assert!(storage.get_block(0).is_some() && storage.get_block(3).is_none() && storage.get_undo(&main[3].hash).is_none());
+        }
+        drop(stores);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
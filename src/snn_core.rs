@@ -1,66 +1,89 @@
 use tokio::sync::RwLock;
 use rand::{Rng, SeedableRng, rngs::StdRng};
+use rand_chacha::ChaCha20Rng;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use crate::storage::Storage;
+use sha2::{Sha256, Digest};
+use crate::storage::ChainStore;
 use crate::oracle::Oracle;
 use crate::llm::LLMBridge;
 use crate::cache::SmartCache;
-use regex::Regex;
+
+// Mạng chuẩn dùng chung cho mọi node để ai cũng tái lập được proof-of-neural-work.
+// Dùng ChaCha20Rng (luồng số cố định theo seed giữa các phiên bản rand), không dùng StdRng.
+const CANONICAL_SEED: u64 = 0x5041_5050_4150_534E; // "PAPPAPSN"
+pub const CANONICAL_NEURONS: usize = 1000;
+/// Số bước train_step chạy cho mỗi block.
+pub const WORK_STEPS: usize = 16;
 
 #[derive(Clone, Debug)]
 pub struct BioNeuron {
     pub potential: f32, pub threshold: f32, pub decay: f32,
-    pub refractory_timer: u8, pub sensitivity: f32, 
+    pub sensitivity: f32, 
 }
 
 pub struct SNNCore {
     neurons: RwLock<Vec<BioNeuron>>,
-    storage: Arc<Storage>,
+    storage: Arc<dyn ChainStore>,
     oracle: Oracle,
     llm: LLMBridge,
     cache: SmartCache,
     total_neurons: usize,
-    momentum: RwLock<f32>,
+}
+
+/// Mạng neuron chuẩn: độ nhạy sinh từ `CANONICAL_SEED` nên giống hệt nhau trên mọi node.
+pub fn canonical_network() -> Vec<BioNeuron> {
+    let mut rng = ChaCha20Rng::seed_from_u64(CANONICAL_SEED);
+    (0..CANONICAL_NEURONS)
+        .map(|_| BioNeuron { potential: -70.0, threshold: -55.0, decay: 0.95, sensitivity: rng.gen_range(0.5..1.5) })
+        .collect()
+}
+
+fn step<R: Rng>(neurons: &mut [BioNeuron], intensity: f32, rng: &mut R) -> f32 {
+    let mut active = 0.0;
+    for n in neurons.iter_mut() {
+        n.potential += intensity * n.sensitivity + rng.gen_range(-0.1..0.1);
+        if n.potential >= n.threshold { n.potential = -85.0; active += 1.0; } else { n.potential *= n.decay; }
+    }
+    active
+}
+
+/// Proof-of-neural-work của block: chạy `WORK_STEPS` bước trên mạng chuẩn với cường độ và nhiễu từ ChaCha20Rng
+/// seed theo SHA256(prev_hash, miner, slot). Kết quả là số lần neuron phát xung, peer nào cũng chạy lại được.
+pub fn neural_work(prev_hash: &str, miner_pqc_pubkey: &str, slot: u64) -> f32 {
+    let digest = Sha256::digest(format!("{}:{}:{}", prev_hash, miner_pqc_pubkey, slot));
+    let mut rng = ChaCha20Rng::from_seed(digest.into());
+    let mut neurons = canonical_network();
+    (0..WORK_STEPS).map(|_| {
+        // Kích thích hỗn hợp hưng phấn/ức chế để số xung thực sự phụ thuộc seed
+        let intensity = rng.gen_range(-6.0..6.0);
+        step(&mut neurons, intensity, &mut rng)
+    }).sum()
 }
 
 impl SNNCore {
-    pub fn new(storage: Arc<Storage>, cache: SmartCache) -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn new(storage: Arc<dyn ChainStore>, cache: SmartCache) -> Self {
         let neuron_count = 1_000_000;
         println!("?? SNN CORE ONLINE | Neurons: {}", neuron_count);
 
-        let mut neurons = Vec::with_capacity(neuron_count);
-        for _ in 0..1000 {
-            neurons.push(BioNeuron { potential: -70.0, threshold: -55.0, decay: 0.95, refractory_timer: 0, sensitivity: rng.gen_range(0.5..1.5) });
-        }
-
         Self {
-            neurons: RwLock::new(neurons),
+            neurons: RwLock::new(canonical_network()),
             storage,
             oracle: Oracle::new(),
             llm: LLMBridge::new(),
             cache,
             total_neurons: neuron_count,
-            momentum: RwLock::new(1.0),
         }
     }
 
     pub async fn train_step(&self, intensity: f32) -> f32 {
         let mut neurons = self.neurons.write().await;
-        let mut rng = rand::thread_rng();
-        let mut active = 0.0;
-        for n in neurons.iter_mut() {
-            n.potential += intensity * n.sensitivity + rng.gen_range(-0.1..0.1);
-            if n.potential >= n.threshold { n.potential = -85.0; active += 1.0; } else { n.potential *= n.decay; }
-        }
-        active
+        step(&mut neurons, intensity, &mut rand::thread_rng())
     }
 
-    pub async fn forward(&self, i: f32) -> f32 { self.train_step(i).await }
     pub async fn stats(&self) -> (usize, f32) { (self.total_neurons, 1024.0) }
-    pub async fn learn(&self, k: String, v: String) { self.storage.learn_fact(&k, &v); }
+    pub async fn prune_cache(&self) { self.cache.prune().await; }
     
     pub async fn process_text(&self, text: &str) -> (f32, String, String) {
         // (Gi? nguyên logic x? lý text nhu các phiên b?n tru?c)
@@ -75,8 +98,8 @@ impl SNNCore {
         if let Ok(res) = self.oracle.smart_search(text).await { if !res.contains("Không tìm th?y") { ans = res; } }
         if ans.is_empty() { if let Ok(res) = self.llm.ask_ai(text).await { ans = res; } }
         
+        // Câu trả lời tự tìm chỉ vào cache của node; tri thức lâu dài phải đi qua giao dịch Knowledge
         if !ans.is_empty() { 
-            self.learn(text.into(), ans.clone()).await; 
             self.cache.set(text.into(), ans.clone()).await; 
         } else { ans = "Không tìm th?y.".into(); }
         
@@ -0,0 +1,433 @@
+use crate::storage::ChainStore;
+use crate::governance::{GovernanceParams, NeuroDAO, VoteChoice};
+use crate::transaction::{Transaction, TxPayload};
+use crate::params::ChainParams;
+use crate::ethics::EthicsFilter;
+use crate::webnode;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Account {
+    pub balance: u64,
+    pub nonce: u64,
+    /// Số dư đang stake (khóa, không chuyển được).
+    pub staked: u64,
+}
+
+/// Thay đổi trạng thái của một block: giá trị mới để ghi và giá trị cũ để hoàn tác khi reorg.
+/// `data_*` là trạng thái dạng khóa-giá trị của các module khác ngoài ledger (vd. governance).
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StateDiff {
+    pub after: BTreeMap<String, Account>,
+    pub before: BTreeMap<String, Option<Account>>,
+    pub data_after: BTreeMap<String, Option<Vec<u8>>>,
+    pub data_before: BTreeMap<String, Option<Vec<u8>>>,
+}
+
+/// Bản hoàn tác lưu ở `undo:{hash}`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct StateUndo {
+    pub accounts: BTreeMap<String, Option<Account>>,
+    pub data: BTreeMap<String, Option<Vec<u8>>>,
+}
+
+/// Tiền tố dữ liệu tri thức AI trong state: `ai:mem:{keyword viết thường}` -> câu trả lời (UTF-8).
+pub const KNOWLEDGE_PREFIX: &str = "ai:mem:";
+
+pub fn knowledge_key(keyword: &str) -> String {
+    format!("{}{}", KNOWLEDGE_PREFIX, keyword.to_lowercase())
+}
+
+/// Lớp phủ trong bộ nhớ trên trạng thái đã lưu, dùng khi dựng hoặc kiểm tra block ở `height`.
+pub struct StateView<'a> {
+    storage: &'a dyn ChainStore,
+    diff: StateDiff,
+    governance: &'a GovernanceParams,
+    pub height: u64,
+}
+
+impl<'a> StateView<'a> {
+    pub fn new(storage: &'a dyn ChainStore, height: u64, governance: &'a GovernanceParams) -> Self {
+        Self { storage, diff: StateDiff::default(), governance, height }
+    }
+
+    pub fn get_data(&self, key: &str) -> Option<Vec<u8>> {
+        match self.diff.data_after.get(key) {
+            Some(v) => v.clone(),
+            None => self.storage.get_state_data(key),
+        }
+    }
+
+    pub fn set_data(&mut self, key: &str, value: Option<Vec<u8>>) {
+        if !self.diff.data_before.contains_key(key) {
+            self.diff.data_before.insert(key.to_string(), self.storage.get_state_data(key));
+        }
+        self.diff.data_after.insert(key.to_string(), value);
+    }
+
+    /// Mọi cặp khóa-giá trị có tiền tố `prefix` (đã lưu + lớp phủ), theo thứ tự khóa.
+    pub fn scan_data(&self, prefix: &str) -> BTreeMap<String, Vec<u8>> {
+        let mut data: BTreeMap<String, Vec<u8>> = self.storage.scan_state_data(prefix).into_iter().collect();
+        for (k, v) in self.diff.data_after.range(prefix.to_string()..).take_while(|(k, _)| k.starts_with(prefix)) {
+            match v {
+                Some(v) => { data.insert(k.clone(), v.clone()); }
+                None => { data.remove(k); }
+            }
+        }
+        data
+    }
+
+    /// Tổng stake = tổng đã lưu (`StateSummary`) cộng chênh lệch của lớp phủ.
+    pub fn total_staked(&self) -> u64 {
+        let stored: u64 = self.diff.before.values().flatten().map(|a| a.staked).sum();
+        let current: u64 = self.diff.after.values().map(|a| a.staked).sum();
+        self.storage.state_summary().total_staked - stored + current
+    }
+
+    pub fn get(&self, address: &str) -> Account {
+        match self.diff.after.get(address) {
+            Some(acct) => acct.clone(),
+            None => self.storage.get_account(address).unwrap_or_default(),
+        }
+    }
+
+    fn set(&mut self, address: &str, acct: Account) {
+        if !self.diff.before.contains_key(address) {
+            self.diff.before.insert(address.to_string(), self.storage.get_account(address));
+        }
+        self.diff.after.insert(address.to_string(), acct);
+    }
+
+    /// Áp một giao dịch thường: đủ phí tối thiểu và qua bộ lọc nội dung theo tham số chuỗi hiện hành, đúng nonce kế tiếp,
+    /// đủ số dư cho phí + phần chi của payload, rồi chuyển trạng thái theo loại. Tri thức được ghi vào dữ liệu state
+    /// (`knowledge_key`) nên nằm trong state root và được hoàn tác khi reorg. Phí không bị đốt mà được cộng vào coinbase của producer.
+    pub fn apply_tx(&mut self, tx: &Transaction) -> Result<(), String> {
+        if tx.is_coinbase() {
+            return Err("Coinbase chỉ được đứng đầu block".to_string());
+        }
+        tx.check_payload()?;
+        let params = ChainParams::load(self)?;
+        if tx.fee < params.min_fee {
+            return Err(format!("Phí {} thấp hơn phí tối thiểu {}", tx.fee, params.min_fee));
+        }
+        for text in tx.payload.texts() { EthicsFilter::check_with(text, &params.ethics_blacklist)?; }
+        let from = tx.sender_address().ok_or("Địa chỉ người gửi không hợp lệ")?;
+        let mut sender = self.get(&from);
+        if tx.nonce != sender.nonce {
+            return Err(format!("Sai nonce: cần {}, nhận {}", sender.nonce, tx.nonce));
+        }
+        let total = tx.payload.spend().checked_add(tx.fee).ok_or("Tràn số amount + fee")?;
+        if sender.balance < total {
+            return Err(format!("Không đủ số dư: có {}, cần {}", sender.balance, total));
+        }
+        sender.balance -= total;
+        sender.nonce += 1;
+
+        match &tx.payload {
+            TxPayload::Transfer { receiver, amount } => {
+                self.set(&from, sender);
+                let mut to = self.get(receiver);
+                to.balance = to.balance.checked_add(*amount).ok_or("Tràn số dư người nhận")?;
+                self.set(receiver, to);
+            }
+            TxPayload::Stake { amount } => {
+                sender.staked = sender.staked.checked_add(*amount).ok_or("Tràn số stake")?;
+                self.set(&from, sender);
+            }
+            TxPayload::Unstake { amount } => {
+                if sender.staked < *amount {
+                    return Err(format!("Không đủ stake: có {}, cần {}", sender.staked, amount));
+                }
+                sender.staked -= amount;
+                sender.balance = sender.balance.checked_add(*amount).ok_or("Tràn số dư")?;
+                self.set(&from, sender);
+            }
+            TxPayload::Proposal { title, description } => {
+                let governance = self.governance;
+                NeuroDAO::propose(self, governance, &from, title, description, None)?;
+                self.set(&from, sender);
+            }
+            TxPayload::ParamProposal { title, description, change } => {
+                let governance = self.governance;
+                NeuroDAO::propose(self, governance, &from, title, description, Some(change.clone()))?;
+                self.set(&from, sender);
+            }
+            TxPayload::Vote { proposal_id, approve } => {
+                NeuroDAO::vote(self, &from, *proposal_id, VoteChoice::from_approve(*approve))?;
+                self.set(&from, sender);
+            }
+            TxPayload::CastVote { proposal_id, choice } => {
+                NeuroDAO::vote(self, &from, *proposal_id, *choice)?;
+                self.set(&from, sender);
+            }
+            TxPayload::Delegate { delegate } => {
+                NeuroDAO::delegate(self, &from, delegate)?;
+                self.set(&from, sender);
+            }
+            TxPayload::Undelegate => {
+                NeuroDAO::undelegate(self, &from)?;
+                self.set(&from, sender);
+            }
+            TxPayload::Knowledge { keyword, answer } => {
+                self.set_data(&knowledge_key(keyword), Some(answer.as_bytes().to_vec()));
+                self.set(&from, sender);
+            }
+            TxPayload::WebWork { epoch, worker, index, answer } => {
+                webnode::record_work(self, *epoch, worker, *index, *answer)?;
+                self.set(&from, sender);
+            }
+            TxPayload::Coinbase { .. } => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Cộng phần thưởng + phí cho producer từ giao dịch coinbase.
+    pub fn apply_coinbase(&mut self, tx: &Transaction) -> Result<(), String> {
+        let (receiver, amount) = match &tx.payload {
+            TxPayload::Coinbase { receiver, amount } if tx.is_coinbase() => (receiver, *amount),
+            _ => return Err("Không phải coinbase".to_string()),
+        };
+        let mut to = self.get(receiver);
+        to.balance = to.balance.checked_add(amount).ok_or("Tràn số dư producer")?;
+        self.set(receiver, to);
+        Ok(())
+    }
+
+    /// Rút phần thưởng block khỏi quỹ thưởng (đối ứng với coinbase để tổng cung không đổi).
+    pub fn release_reward(&mut self, pool: &str, amount: u64) -> Result<(), String> {
+        if amount == 0 { return Ok(()); }
+        let mut from = self.get(pool);
+        if from.balance < amount {
+            return Err(format!("Quỹ thưởng {} không đủ: có {}, cần {}", pool, from.balance, amount));
+        }
+        from.balance -= amount;
+        self.set(pool, from);
+        Ok(())
+    }
+
+    /// State root của trạng thái đã lưu sau khi gộp lớp phủ: chỉ băm lại các nhóm lá có khóa bị lớp phủ đổi.
+    pub fn root(&self) -> String {
+        self.storage.state_summary().root_with(&self.diff)
+    }
+
+    pub fn into_diff(self) -> StateDiff {
+        self.diff
+    }
+}
+
+impl StateDiff {
+    /// Thay đổi tổng cung (số dư + stake) do block gây ra; phần thưởng rút từ quỹ nên luôn phải bằng 0.
+    pub fn supply_delta(&self) -> i128 {
+        let after: i128 = self.after.values().map(|a| a.balance as i128 + a.staked as i128).sum();
+        let before: i128 = self.before.values().flatten().map(|a| a.balance as i128 + a.staked as i128).sum();
+        after - before
+    }
+}
+
+// Số nhóm lá của state root: đổi một khóa chỉ phải băm lại nhóm chứa nó
+const ROOT_BUCKETS: usize = 256;
+
+fn account_leaf(addr: &str, acct: &Account) -> Option<[u8; 32]> {
+    if *acct == Account::default() { return None; }
+    Some(Sha256::digest(format!("{}:{}:{}:{};", addr, acct.balance, acct.nonce, acct.staked)).into())
+}
+
+fn data_leaf(key: &str, value: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}:{}:", key, value.len()));
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+fn bucket_of(leaf_key: &str) -> usize {
+    Sha256::digest(leaf_key)[0] as usize
+}
+
+/// Tổng hợp của trạng thái đã lưu tại đỉnh `tip`: hash lá của từng tài khoản/dữ liệu chia theo nhóm, hash từng nhóm
+/// và tổng stake. Kho giữ bản của đỉnh hiện tại và cập nhật theo `StateDiff` khi nối block, nên `StateView::root`
+/// và `total_staked` không phải đọc lại toàn bộ trạng thái.
+#[derive(Clone, Debug)]
+pub struct StateSummary {
+    pub tip: String,
+    pub total_staked: u64,
+    buckets: Vec<BTreeMap<String, [u8; 32]>>,
+    hashes: Vec<[u8; 32]>,
+}
+
+impl StateSummary {
+    pub fn build(tip: &str, accounts: impl IntoIterator<Item = (String, Account)>, data: impl IntoIterator<Item = (String, Vec<u8>)>) -> Self {
+        let mut summary = Self { tip: tip.to_string(), total_staked: 0, buckets: vec![BTreeMap::new(); ROOT_BUCKETS], hashes: Vec::new() };
+        for (addr, acct) in accounts {
+            summary.total_staked += acct.staked;
+            summary.set_leaf(format!("acct:{}", addr), account_leaf(&addr, &acct));
+        }
+        for (key, value) in data { summary.set_leaf(format!("sd:{}", key), Some(data_leaf(&key, &value))); }
+        summary.hashes = summary.buckets.iter().map(Self::hash_bucket).collect();
+        summary
+    }
+
+    fn set_leaf(&mut self, leaf_key: String, leaf: Option<[u8; 32]>) -> usize {
+        let bucket = bucket_of(&leaf_key);
+        match leaf {
+            Some(l) => { self.buckets[bucket].insert(leaf_key, l); }
+            None => { self.buckets[bucket].remove(&leaf_key); }
+        }
+        bucket
+    }
+
+    // Lá của lớp phủ theo khóa lá (None = tài khoản rỗng hoặc dữ liệu bị xóa)
+    fn overlay(diff: &StateDiff) -> impl Iterator<Item = (String, Option<[u8; 32]>)> + '_ {
+        let accounts = diff.after.iter().map(|(addr, acct)| (format!("acct:{}", addr), account_leaf(addr, acct)));
+        let data = diff.data_after.iter().map(|(key, value)| (format!("sd:{}", key), value.as_ref().map(|v| data_leaf(key, v))));
+        accounts.chain(data)
+    }
+
+    fn hash_bucket(bucket: &BTreeMap<String, [u8; 32]>) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for leaf in bucket.values() { hasher.update(leaf); }
+        hasher.finalize().into()
+    }
+
+    fn combine(hashes: &[[u8; 32]]) -> String {
+        let mut hasher = Sha256::new();
+        for h in hashes { hasher.update(h); }
+        hex::encode(hasher.finalize())
+    }
+
+    pub fn root(&self) -> String {
+        Self::combine(&self.hashes)
+    }
+
+    /// Root của trạng thái này sau khi gộp `diff`, không sửa bản tổng hợp.
+    pub fn root_with(&self, diff: &StateDiff) -> String {
+        let mut touched: BTreeMap<usize, BTreeMap<String, [u8; 32]>> = BTreeMap::new();
+        for (leaf_key, leaf) in Self::overlay(diff) {
+            let bucket = touched.entry(bucket_of(&leaf_key)).or_insert_with_key(|b| self.buckets[*b].clone());
+            match leaf {
+                Some(l) => { bucket.insert(leaf_key, l); }
+                None => { bucket.remove(&leaf_key); }
+            }
+        }
+        let mut hashes = self.hashes.clone();
+        for (b, bucket) in &touched { hashes[*b] = Self::hash_bucket(bucket); }
+        Self::combine(&hashes)
+    }
+
+    /// Nối block `tip` có thay đổi `diff` vào bản tổng hợp của block cha.
+    pub fn apply(&mut self, tip: &str, diff: &StateDiff) {
+        let stored: u64 = diff.before.values().flatten().map(|a| a.staked).sum();
+        let current: u64 = diff.after.values().map(|a| a.staked).sum();
+        self.total_staked = self.total_staked - stored + current;
+        let leaves: Vec<_> = Self::overlay(diff).collect();
+        let dirty: BTreeSet<usize> = leaves.into_iter().map(|(leaf_key, leaf)| self.set_leaf(leaf_key, leaf)).collect();
+        for b in dirty { self.hashes[b] = Self::hash_bucket(&self.buckets[b]); }
+        self.tip = tip.to_string();
+    }
+}
+
+/// State root = SHA256 của hash 256 nhóm lá; mỗi nhóm là SHA256 các lá theo thứ tự khóa lá (`acct:{địa chỉ}` với tài khoản
+/// khác rỗng, `sd:{khóa}` với dữ liệu module), nhóm của lá chọn theo byte đầu SHA256(khóa lá).
+pub fn compute_root(accounts: &BTreeMap<String, Account>, data: &BTreeMap<String, Vec<u8>>) -> String {
+    StateSummary::build("", accounts.clone(), data.clone()).root()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::genesis::{ChainSpec, EmissionSpec};
+    use crate::memstore::MemoryStore;
+    use crate::storage::SledStore;
+    use crate::transaction::MIN_FEE;
+    use crate::wallet::Wallet;
+
+    const BOB: &str = "PAPPAP00000000000000000000000000000002";
+
+    fn spec(alice: &Wallet) -> ChainSpec {
+        ChainSpec {
+            chain_id: "pappap-test".to_string(),
+            genesis_timestamp: 0,
+            block_interval_ms: 800,
+            validators: Vec::new(),
+            balances: BTreeMap::from([("COMMUNITY_POOL".to_string(), 1_000_000), (alice.address.clone(), 1_000)]),
+            emission: EmissionSpec::default(),
+            governance: GovernanceParams::default(),
+        }
+    }
+
+    fn genesis(storage: &dyn ChainStore, spec: &ChainSpec) -> Block {
+        let block = spec.genesis_block();
+        storage.init_genesis(&block, &spec.genesis_accounts(), &spec.genesis_data());
+        block
+    }
+
+    // Root tính lại từ đầu trên toàn bộ trạng thái đã lưu, để so với bản tổng hợp cập nhật dần
+    fn full_root(storage: &dyn ChainStore) -> String {
+        compute_root(&storage.load_accounts().into_iter().collect(), &storage.scan_state_data("").into_iter().collect())
+    }
+
+    #[test]
+    fn apply_tx_checks_nonce_and_balance_and_moves_stake() {
+        let alice = Wallet::new();
+        let spec = spec(&alice);
+        let storage = MemoryStore::new();
+        genesis(&storage, &spec);
+        let governance = GovernanceParams::default();
+        let mut view = StateView::new(&storage, 1, &governance);
+
+        view.apply_tx(&Transaction::signed(&alice, 0, MIN_FEE, TxPayload::Transfer { receiver: BOB.to_string(), amount: 100 })).unwrap();
+        assert_eq!(view.get(&alice.address), Account { balance: 1_000 - 100 - MIN_FEE, nonce: 1, staked: 0 });
+        assert_eq!(view.get(BOB).balance, 100);
+
+        // Nonce đã dùng, nonce nhảy cóc, phí dưới mức tối thiểu và chi quá số dư đều bị từ chối mà không đổi trạng thái
+        let transfer = |nonce, fee, amount| Transaction::signed(&alice, nonce, fee, TxPayload::Transfer { receiver: BOB.to_string(), amount });
+        assert!(view.apply_tx(&transfer(0, MIN_FEE, 1)).unwrap_err().contains("nonce"));
+        assert!(view.apply_tx(&transfer(2, MIN_FEE, 1)).unwrap_err().contains("nonce"));
+        assert!(view.apply_tx(&transfer(1, MIN_FEE - 1, 1)).unwrap_err().contains("tối thiểu"));
+        assert!(view.apply_tx(&transfer(1, MIN_FEE, 900)).unwrap_err().contains("số dư"));
+        assert_eq!(view.get(&alice.address).nonce, 1);
+
+        view.apply_tx(&Transaction::signed(&alice, 1, MIN_FEE, TxPayload::Stake { amount: 300 })).unwrap();
+        assert_eq!((view.get(&alice.address).staked, view.total_staked()), (300, 300));
+        assert!(view.apply_tx(&Transaction::signed(&alice, 2, MIN_FEE, TxPayload::Unstake { amount: 301 })).unwrap_err().contains("stake"));
+        view.apply_tx(&Transaction::signed(&alice, 2, MIN_FEE, TxPayload::Unstake { amount: 100 })).unwrap();
+        assert_eq!(view.get(&alice.address), Account { balance: 1_000 - 100 - 300 + 100 - 3 * MIN_FEE, nonce: 3, staked: 200 });
+        assert_eq!(view.total_staked(), 200);
+        // Phí chưa về ai (coinbase của block cộng lại), nên tổng cung giảm đúng bằng phí
+        assert_eq!(view.into_diff().supply_delta(), -3 * MIN_FEE as i128);
+    }
+
+    #[test]
+    fn committed_diff_keeps_summary_in_step_and_undo_restores_state() {
+        let alice = Wallet::new();
+        let spec = spec(&alice);
+        let path = std::env::temp_dir().join(format!("pappap-state-{}", uuid::Uuid::new_v4()));
+        let stores: [Box<dyn ChainStore>; 2] = [Box::new(MemoryStore::new()), Box::new(SledStore::new(path.to_str().unwrap()).unwrap())];
+        let governance = GovernanceParams::default();
+        for storage in &stores {
+            let genesis = genesis(&**storage, &spec);
+            assert_eq!(storage.state_root(), genesis.state_root);
+
+            let mut view = StateView::new(&**storage, 1, &governance);
+            view.apply_tx(&Transaction::signed(&alice, 0, MIN_FEE, TxPayload::Stake { amount: 400 })).unwrap();
+            view.apply_tx(&Transaction::signed(&alice, 1, MIN_FEE, TxPayload::Knowledge { keyword: "Pappap".to_string(), answer: "chuỗi AI".to_string() })).unwrap();
+            let root = view.root();
+            let block = Block::new(1, 1, genesis.hash.clone(), root.clone(), Vec::new(), 0.0, "test".to_string(), String::new());
+            storage.commit_block(&block, &view.into_diff());
+
+            // Bản tổng hợp cập nhật theo diff khớp root tính lại từ đầu và root của lớp phủ trước khi ghi
+            assert_eq!((storage.state_root(), full_root(&**storage)), (root.clone(), root));
+            assert_eq!(storage.state_summary().total_staked, 400);
+            assert_eq!(storage.recall_fact("pappap").as_deref(), Some("chuỗi AI"));
+
+            storage.disconnect_tip(&block);
+            assert_eq!((storage.state_root(), full_root(&**storage)), (genesis.state_root.clone(), genesis.state_root.clone()));
+            assert_eq!(storage.get_account(&alice.address), Some(Account { balance: 1_000, ..Default::default() }));
+            assert_eq!((storage.state_summary().total_staked, storage.recall_fact("pappap")), (0, None));
+        }
+        drop(stores);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -1,7 +1,18 @@
-use sled::Db;
+use sled::{Db, Tree, Transactional};
+use sled::transaction::{ConflictableTransactionError, TransactionalTree};
+
+type TxResult = Result<(), ConflictableTransactionError<()>>;
 use crate::block::Block;
-use serde::{Serialize, Deserialize};
+use crate::state::{Account, StateDiff, StateSummary, StateUndo, knowledge_key};
+use crate::transaction::Transaction;
+use crate::finality::{QuorumCertificate, VoteKind};
+use crate::snapshot::Snapshot;
+use crate::migrations::{self, SCHEMA_VERSION};
+use crate::params::ChainParams;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use std::str;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -12,65 +23,434 @@ pub struct NodeStats {
     pub reputation: u64,      // Điểm uy tín
 }
 
+impl NodeStats {
+    /// Thống kê của node mới chạy lần đầu.
+    pub fn fresh() -> Self {
+        NodeStats {
+            first_seen: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            total_starts: 0,
+            total_blocks: 0,
+            reputation: 100,
+        }
+    }
+}
+
+/// Vị trí của giao dịch trên chuỗi chính.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct TxLocation {
+    pub height: u64,
+    pub position: u32,
+}
+
+// Khóa chỉ mục địa chỉ: {address}:{height:020}:{position:010} -> tx id, duyệt ngược để lấy mới nhất trước
+fn address_index_key(address: &str, height: u64, position: u32) -> String {
+    format!("{}:{:020}:{:010}", address, height, position)
+}
+
+// Giải mã bản ghi theo schema hiện tại. Bản ghi định dạng cũ đã được migration xử lý lúc mở DB,
+// nên lỗi ở đây là DB hỏng: báo kèm tên khóa thay vì lặng lẽ bỏ qua.
+fn decode<T: DeserializeOwned>(key: &[u8], raw: &[u8]) -> Result<T, String> {
+    bincode::deserialize(raw).map_err(|e| {
+        format!("Bản ghi '{}' không đọc được theo schema v{}: {}. DB hỏng: hãy xóa pappap_data và đồng bộ lại", String::from_utf8_lossy(key), SCHEMA_VERSION, e)
+    })
+}
+
+// Đọc lúc đang chạy: mọi bản ghi đã được kiểm lúc mở (`SledStore::verify_records`), lỗi ở đây chỉ được ghi log
+fn read<T: DeserializeOwned>(key: &[u8], raw: &[u8]) -> Option<T> {
+    decode(key, raw).map_err(|e| println!("⚠️  STORAGE: {}", e)).ok()
+}
+
+/// Kho dữ liệu của node: chuỗi block, trạng thái, danh tính, thống kê và bộ nhớ AI.
+/// `SledStore` là bản lưu trên đĩa; `memstore::MemoryStore` giữ mọi thứ trong RAM (node tạm, test nhiều node trong một process).
+/// Mọi thao tác ghi chuỗi chính phải nguyên tử: không bao giờ để lộ block mà thiếu trạng thái hay chỉ mục của nó.
+pub trait ChainStore: Send + Sync {
+    // --- QUẢN LÝ IDENTITY (KHÓA BÍ MẬT) ---
+    /// Khóa libp2p dạng thô của DB cũ, chỉ còn đọc để chuyển sang keystore.
+    fn load_node_secret(&self) -> Option<Vec<u8>>;
+    fn remove_node_secret(&self);
+    /// Keystore đã mã hóa (khóa libp2p + Dilithium), xem `keystore::NodeKeys`.
+    fn save_keystore(&self, data: &[u8]);
+    fn load_keystore(&self) -> Option<Vec<u8>>;
+
+    // --- QUẢN LÝ THỐNG KÊ (STATS) ---
+    fn load_stats(&self) -> NodeStats;
+    fn save_stats(&self, stats: &NodeStats);
+
+    // --- QUẢN LÝ BLOCKCHAIN ---
+    /// Nối block vào đỉnh chuỗi chính: thay đổi trạng thái + bản hoàn tác, block, chỉ mục,
+    /// đỉnh mới và thống kê được ghi cùng lúc.
+    fn commit_block(&self, block: &Block, diff: &StateDiff);
+    /// Gỡ block đỉnh khỏi chuỗi chính: hoàn tác trạng thái, xóa block và chỉ mục, block cha trở thành đỉnh.
+    /// Block vẫn còn trong cây block (`get_block_by_hash`).
+    fn disconnect_tip(&self, block: &Block);
+    /// Kiểm tra đỉnh chuỗi lúc khởi động: sửa đỉnh ghi dở, dựng lại chỉ mục nếu lệch,
+    /// và từ chối chạy nếu trạng thái không khớp state_root của block đỉnh.
+    fn check_consistency(&self) -> Result<(), String>;
+    /// Block chuỗi chính ở height `index`.
+    fn get_block(&self, index: u64) -> Option<Block>;
+    fn get_height(&self) -> u64;
+    fn get_last_hash(&self) -> String;
+
+    /// Ghi block #0 và phân bổ ban đầu vào kho trống.
+    fn init_genesis(&self, block: &Block, accounts: &BTreeMap<String, Account>, data: &BTreeMap<String, Vec<u8>>) {
+        let data_after = data.iter().map(|(k, v)| (k.clone(), Some(v.clone()))).collect();
+        let diff = StateDiff { after: accounts.clone(), data_after, ..Default::default() };
+        self.store_block(block, 0.0);
+        self.commit_block(block, &diff);
+    }
+
+    fn get_recent_blocks(&self, limit: u64) -> Vec<Block> {
+        let height = self.get_height();
+        let start = height.saturating_sub(limit);
+        // Chỉ bỏ qua block đã prune; block không đọc được là lỗi (xem `decode`)
+        (start..=height).rev().filter_map(|i| self.get_block(i)).collect()
+    }
+
+    // --- FINALITY ---
+    /// Ghi height đã chốt cùng chứng chỉ quorum.
+    fn set_finalized(&self, qc: &QuorumCertificate);
+    fn get_qc(&self, height: u64) -> Option<QuorumCertificate>;
+    /// (height, hash) của block đã chốt gần nhất; (0, "") nếu chưa có.
+    fn get_finalized(&self) -> (u64, String);
+    /// (height, hash) của phiếu `kind` cuối cùng node này đã ký.
+    fn get_last_signed(&self, kind: VoteKind) -> Option<(u64, String)>;
+    /// Ghi bền phiếu sắp ký trước khi phát, để sau khi khởi động lại không ký block khác ở cùng height.
+    fn set_last_signed(&self, kind: VoteKind, height: u64, hash: &str);
+
+    // --- NHÁNH PHỤ & FORK CHOICE ---
+    /// Lưu block theo hash kèm tổng công (spike_score tích lũy) của nhánh chứa nó.
+    fn store_block(&self, block: &Block, total_work: f64);
+    fn get_block_by_hash(&self, hash: &str) -> Option<Block>;
+    fn get_total_work(&self, hash: &str) -> Option<f64>;
+    /// Xóa block không hợp lệ khỏi cây block để không bị chọn lại.
+    fn forget_block(&self, hash: &str);
+
+    // --- CHỈ MỤC ---
+    /// Height của block `hash` nếu nó nằm trên chuỗi chính.
+    fn get_canonical_height(&self, hash: &str) -> Option<u64>;
+    fn get_tx_location(&self, id: &str) -> Option<TxLocation>;
+    /// Giao dịch trên chuỗi chính có liên quan tới `address`, mới nhất trước: (tổng số, trang [offset, offset + limit)).
+    fn get_address_txs(&self, address: &str, offset: usize, limit: usize) -> (usize, Vec<(TxLocation, Transaction)>);
+
+    // --- PRUNING & SNAPSHOT ---
+    /// Height cao nhất đã bị xóa (block 1..=height không còn, block #0 luôn giữ lại); 0 nếu chưa prune.
+    fn get_pruned_height(&self) -> u64;
+    /// Bản hoàn tác của block chuỗi chính `hash` (giá trị trước khi áp block); không còn nếu block đã prune.
+    fn get_undo(&self, hash: &str) -> Option<StateUndo>;
+    /// Xóa các block chuỗi chính dưới `keep_from` (block, bản hoàn tác, QC, chỉ mục) cùng mọi block nhánh phụ
+    /// ở các height đã prune. Trả về số block chuỗi chính đã xóa.
+    fn prune_below(&self, keep_from: u64) -> u64;
+    /// Dựng kho trống từ snapshot đã kiểm tra: block #0, block đỉnh của snapshot (coi như đã chốt), trạng thái và tri thức,
+    /// các block ở giữa coi như đã prune.
+    fn restore_snapshot(&self, genesis: &Block, snap: &Snapshot) -> Result<(), String>;
+
+    // --- TRẠNG THÁI TÀI KHOẢN (LEDGER) ---
+    fn get_account(&self, address: &str) -> Option<Account>;
+    fn load_accounts(&self) -> Vec<(String, Account)>;
+    /// Dữ liệu trạng thái của module (governance, params...).
+    fn get_state_data(&self, key: &str) -> Option<Vec<u8>>;
+    fn scan_state_data(&self, prefix: &str) -> Vec<(String, Vec<u8>)>;
+
+    /// Tổng hợp (hash lá, tổng stake) của trạng thái đã lưu tại đỉnh hiện tại, dựng lại khi đỉnh đổi mà chưa cập nhật kịp.
+    fn state_summary(&self) -> Arc<StateSummary>;
+
+    /// State root của trạng thái đã lưu (xem `state::compute_root`).
+    fn state_root(&self) -> String {
+        self.state_summary().root()
+    }
+
+    // --- MEMPOOL ---
+    fn save_mempool_tx(&self, tx: &Transaction, received_at: u64);
+    fn remove_mempool_tx(&self, id: &str);
+    fn load_mempool(&self) -> Vec<(Transaction, u64)>;
+
+    // --- AI MEMORY ---
+    /// Tri thức AI trên chuỗi chính (ghi bằng giao dịch Knowledge, xem `state::knowledge_key`).
+    fn recall_fact(&self, question: &str) -> Option<String> {
+        self.get_state_data(&knowledge_key(question)).and_then(|v| String::from_utf8(v).ok())
+    }
+}
+
+/// Kho trên đĩa (sled): mỗi lần ghi chuỗi chính là một transaction trên cây chính và các cây chỉ mục.
 #[derive(Clone)]
-pub struct Storage {
+pub struct SledStore {
     db: Db,
+    // Chỉ mục phụ của chuỗi chính, cập nhật cùng transaction sled với `block:{index}`
+    hash_index: Tree,
+    tx_index: Tree,
+    address_index: Tree,
+    // Tổng hợp trạng thái của đỉnh; ghi chuỗi chính giữ khóa này suốt lúc ghi để không ai dựng bản tổng hợp từ trạng thái ghi dở
+    summary: Arc<Mutex<Option<Arc<StateSummary>>>>,
 }
 
-impl Storage {
-    pub fn new(path: &str) -> Self {
-        let db = sled::open(path).expect("Không thể mở Database");
-        println!("💾 STORAGE: Connected to '{}'", path);
-        Self { db }
+impl SledStore {
+    /// Mở DB, chạy migration rồi kiểm mọi bản ghi có kiểu: bản ghi không đọc được làm việc mở thất bại
+    /// ngay thay vì lộ ra giữa lúc chạy.
+    pub fn new(path: &str) -> Result<Self, String> {
+        let db = sled::open(path).map_err(|e| format!("Không thể mở Database '{}': {}", path, e))?;
+        migrations::run(&db)?;
+        println!("💾 STORAGE: Connected to '{}' (schema v{})", path, migrations::schema_version(&db));
+        let hash_index = db.open_tree("idx:block_hash").unwrap();
+        let tx_index = db.open_tree("idx:tx").unwrap();
+        let address_index = db.open_tree("idx:address").unwrap();
+        let storage = Self { db, hash_index, tx_index, address_index, summary: Arc::default() };
+        storage.verify_records()?;
+        storage.backfill_indexes();
+        Ok(storage)
     }
 
-    // --- QUẢN LÝ IDENTITY (KHÓA BÍ MẬT) ---
-    pub fn save_node_secret(&self, secret_bytes: &[u8]) {
-        self.db.insert("node:secret_key", secret_bytes).unwrap();
+    fn verify_records(&self) -> Result<(), String> {
+        fn check<T: DeserializeOwned>(tree: &Tree, prefix: &str) -> Result<(), String> {
+            for (key, value) in tree.scan_prefix(prefix).filter_map(|r| r.ok()) { decode::<T>(&key, &value)?; }
+            Ok(())
+        }
+        check::<Block>(&self.db, "block:")?;
+        check::<Block>(&self.db, "blk:")?;
+        check::<StateUndo>(&self.db, "undo:")?;
+        check::<QuorumCertificate>(&self.db, "qc:")?;
+        check::<Account>(&self.db, "acct:")?;
+        check::<(Transaction, u64)>(&self.db, "mempool:")?;
+        check::<(u64, String)>(&self.db, "node:signed:")?;
+        check::<(u64, String)>(&self.db, "chain:finalized")?;
+        check::<NodeStats>(&self.db, "node:stats")?;
+        check::<TxLocation>(&self.tx_index, "")?;
+        ChainParams::check_stored(self)
     }
 
-    pub fn load_node_secret(&self) -> Option<Vec<u8>> {
-        self.db.get("node:secret_key").unwrap().map(|ivec| ivec.to_vec())
+    // DB tạo trước khi có chỉ mục: dựng lại từ các block của chuỗi chính
+    fn backfill_indexes(&self) {
+        if !self.hash_index.is_empty() { return; }
+        let mut count = 0;
+        let first = self.get_pruned_height() + 1;
+        for i in std::iter::once(0).chain(first..=self.get_height()) {
+            if let Some(block) = self.get_block(i) {
+                (&self.hash_index, &self.tx_index, &self.address_index)
+                    .transaction(|(hashes, txs, addrs)| Self::index_block(&block, hashes, txs, addrs, true))
+                    .expect("Không dựng được chỉ mục");
+                count += 1;
+            }
+        }
+        if count > 1 { println!("💾 STORAGE: Dựng chỉ mục cho {} block", count); }
     }
 
-    // --- QUẢN LÝ THỐNG KÊ (STATS) ---
-    pub fn load_stats(&self) -> NodeStats {
-        match self.db.get("node:stats").unwrap() {
-            Some(ivec) => bincode::deserialize(&ivec).unwrap_or(NodeStats {
-                first_seen: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                total_starts: 0,
-                total_blocks: 0,
-                reputation: 100,
-            }),
-            None => NodeStats {
-                first_seen: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
-                total_starts: 0,
-                total_blocks: 0,
-                reputation: 100,
+    fn decode_stats(raw: Option<&[u8]>) -> NodeStats {
+        raw.and_then(|v| read(b"node:stats", v)).unwrap_or_else(NodeStats::fresh)
+    }
+
+    fn write_tip(db: &TransactionalTree, height: u64, hash: &str) -> TxResult {
+        db.insert("chain:height", &height.to_be_bytes())?;
+        db.insert("chain:last_hash", hash.as_bytes())?;
+        Ok(())
+    }
+
+    fn index_block(block: &Block, hashes: &TransactionalTree, txs: &TransactionalTree, addrs: &TransactionalTree, insert: bool) -> TxResult {
+        if insert { hashes.insert(block.hash.as_bytes(), &block.index.to_be_bytes())?; } else { hashes.remove(block.hash.as_bytes())?; }
+        for (position, tx) in block.transactions.iter().enumerate() {
+            let location = TxLocation { height: block.index, position: position as u32 };
+            if insert { txs.insert(tx.id.as_bytes(), bincode::serialize(&location).unwrap())?; } else { txs.remove(tx.id.as_bytes())?; }
+            for addr in tx.addresses() {
+                let key = address_index_key(&addr, block.index, position as u32);
+                if insert { addrs.insert(key.as_bytes(), tx.id.as_bytes())?; } else { addrs.remove(key.as_bytes())?; }
             }
         }
+        Ok(())
     }
 
-    pub fn save_stats(&self, stats: &NodeStats) {
+    fn write_state_data(db: &TransactionalTree, key: &str, value: &Option<Vec<u8>>) -> TxResult {
+        let key = format!("sd:{}", key);
+        match value {
+            Some(v) => { db.insert(key.as_bytes(), v.as_slice())?; }
+            None => { db.remove(key.as_bytes())?; }
+        }
+        Ok(())
+    }
+}
+
+impl ChainStore for SledStore {
+    fn load_node_secret(&self) -> Option<Vec<u8>> {
+        self.db.get("node:secret_key").unwrap().map(|ivec| ivec.to_vec())
+    }
+
+    fn remove_node_secret(&self) {
+        self.db.remove("node:secret_key").unwrap();
+    }
+
+    fn save_keystore(&self, data: &[u8]) {
+        self.db.insert("node:keystore", data).unwrap();
+        self.db.flush().unwrap();
+    }
+
+    fn load_keystore(&self) -> Option<Vec<u8>> {
+        self.db.get("node:keystore").unwrap().map(|ivec| ivec.to_vec())
+    }
+
+    fn load_stats(&self) -> NodeStats {
+        Self::decode_stats(self.db.get("node:stats").unwrap().as_deref())
+    }
+
+    fn save_stats(&self, stats: &NodeStats) {
         let encoded = bincode::serialize(stats).unwrap();
         self.db.insert("node:stats", encoded).unwrap();
     }
 
-    // --- QUẢN LÝ BLOCKCHAIN (Cũ) ---
-    pub fn save_block(&self, block: &Block) {
-        let key = format!("block:{}", block.index);
+    // `block:{index}` chỉ chứa chuỗi chính (canonical); mọi block (kể cả nhánh phụ) nằm ở `blk:{hash}`.
+    // Trạng thái, `undo:{hash}`, block, chỉ mục, đỉnh và thống kê nằm trong cùng một transaction sled.
+    fn commit_block(&self, block: &Block, diff: &StateDiff) {
+        let encoded: Vec<u8> = bincode::serialize(block).unwrap();
+        let accounts: Vec<(String, Vec<u8>)> = diff.after.iter().map(|(addr, acct)| (format!("acct:{}", addr), bincode::serialize(acct).unwrap())).collect();
+        let undo = bincode::serialize(&StateUndo { accounts: diff.before.clone(), data: diff.data_before.clone() }).unwrap();
+        let mut summary = self.summary.lock().unwrap();
+        (&*self.db, &self.hash_index, &self.tx_index, &self.address_index).transaction(|(db, hashes, txs, addrs)| {
+            for (key, value) in &accounts { db.insert(key.as_bytes(), value.as_slice())?; }
+            for (key, value) in &diff.data_after { Self::write_state_data(db, key, value)?; }
+            db.insert(format!("undo:{}", block.hash).as_bytes(), undo.as_slice())?;
+            db.insert(format!("block:{}", block.index).as_bytes(), encoded.as_slice())?;
+            Self::write_tip(db, block.index, &block.hash)?;
+            Self::index_block(block, hashes, txs, addrs, true)?;
+            // Tự động tăng stats khi lưu block
+            let mut stats = Self::decode_stats(db.get("node:stats")?.as_deref());
+            stats.total_blocks += 1;
+            db.insert("node:stats", bincode::serialize(&stats).unwrap())?;
+            Ok(())
+        }).expect("Không ghi được block");
+        *summary = summary.take().filter(|s| s.tip == block.prev_hash).map(|s| {
+            let mut s = Arc::unwrap_or_clone(s);
+            s.apply(&block.hash, diff);
+            Arc::new(s)
+        });
+    }
+
+    // Một transaction: hoàn tác theo `undo:{hash}`, block vẫn còn ở `blk:{hash}`
+    fn disconnect_tip(&self, block: &Block) {
+        // Reorg hiếm: bỏ bản tổng hợp, lần đọc sau dựng lại
+        let mut summary = self.summary.lock().unwrap();
+        *summary = None;
+        (&*self.db, &self.hash_index, &self.tx_index, &self.address_index).transaction(|(db, hashes, txs, addrs)| {
+            let undo_key = format!("undo:{}", block.hash);
+            if let Some(ivec) = db.get(undo_key.as_bytes())? {
+                let undo: StateUndo = read(undo_key.as_bytes(), &ivec).ok_or(ConflictableTransactionError::Abort(()))?;
+                for (addr, acct) in &undo.accounts {
+                    let acct_key = format!("acct:{}", addr);
+                    match acct {
+                        Some(a) => { db.insert(acct_key.as_bytes(), bincode::serialize(a).unwrap())?; }
+                        None => { db.remove(acct_key.as_bytes())?; }
+                    }
+                }
+                for (key, value) in &undo.data { Self::write_state_data(db, key, value)?; }
+                db.remove(undo_key.as_bytes())?;
+            }
+            db.remove(format!("block:{}", block.index).as_bytes())?;
+            Self::write_tip(db, block.index.saturating_sub(1), &block.prev_hash)?;
+            Self::index_block(block, hashes, txs, addrs, false)
+        }).expect("Không gỡ được block đỉnh");
+    }
+
+    fn check_consistency(&self) -> Result<(), String> {
+        let (height, last_hash) = (self.get_height(), self.get_last_hash());
+        let mut tip = height;
+        while tip > 0 && self.get_block(tip).is_none() { tip -= 1; }
+        let mut block = match self.get_block(tip) {
+            Some(b) => b,
+            None if height == 0 => return Ok(()),
+            None => return Err("Không còn block nào trên chuỗi chính".to_string()),
+        };
+        // Block kế tiếp đã ghi nhưng chưa kịp dời đỉnh: nhận nếu trạng thái đã áp đúng block đó
+        let root = self.state_root();
+        while let Some(next) = self.get_block(block.index + 1) {
+            if next.prev_hash != block.hash || next.state_root != root { break; }
+            block = next;
+        }
+        if block.index != height || block.hash != last_hash {
+            println!("⚠️  STORAGE: Đỉnh chuỗi ghi dở (height {}, hash {}), sửa về #{} {}", height, last_hash, block.index, block.hash);
+            self.db.transaction(|db| Self::write_tip(db, block.index, &block.hash)).expect("Không sửa được đỉnh chuỗi");
+        }
+        let mut stale = block.index + 1;
+        while self.db.remove(format!("block:{}", stale).as_bytes()).unwrap().is_some() { stale += 1; }
+        if self.get_canonical_height(&block.hash) != Some(block.index) {
+            println!("⚠️  STORAGE: Chỉ mục lệch với chuỗi chính, dựng lại");
+            for tree in [&self.hash_index, &self.tx_index, &self.address_index] { tree.clear().unwrap(); }
+            self.backfill_indexes();
+        }
+        if root != block.state_root {
+            return Err(format!("Trạng thái không khớp state_root của block đỉnh #{}, DB hỏng: hãy xóa pappap_data và đồng bộ lại", block.index));
+        }
+        self.db.flush().unwrap();
+        Ok(())
+    }
+
+    fn get_block(&self, index: u64) -> Option<Block> {
+        let key = format!("block:{}", index);
+        self.db.get(key.as_bytes()).unwrap().and_then(|ivec| read(key.as_bytes(), &ivec))
+    }
+
+    fn set_finalized(&self, qc: &QuorumCertificate) {
+        self.db.insert(format!("qc:{}", qc.height).as_bytes(), bincode::serialize(qc).unwrap()).unwrap();
+        self.db.insert("chain:finalized", bincode::serialize(&(qc.height, &qc.block_hash)).unwrap()).unwrap();
+    }
+
+    fn get_qc(&self, height: u64) -> Option<QuorumCertificate> {
+        let key = format!("qc:{}", height);
+        self.db.get(key.as_bytes()).unwrap().and_then(|ivec| read(key.as_bytes(), &ivec))
+    }
+
+    fn get_finalized(&self) -> (u64, String) {
+        self.db.get("chain:finalized").unwrap()
+            .and_then(|ivec| read(b"chain:finalized", &ivec))
+            .unwrap_or((0, String::new()))
+    }
+
+    fn get_last_signed(&self, kind: VoteKind) -> Option<(u64, String)> {
+        let key = format!("node:signed:{:?}", kind);
+        self.db.get(key.as_bytes()).unwrap().and_then(|ivec| read(key.as_bytes(), &ivec))
+    }
+
+    fn set_last_signed(&self, kind: VoteKind, height: u64, hash: &str) {
+        self.db.insert(format!("node:signed:{:?}", kind).as_bytes(), bincode::serialize(&(height, hash)).unwrap()).unwrap();
+        self.db.flush().unwrap();
+    }
+
+    fn store_block(&self, block: &Block, total_work: f64) {
         let encoded: Vec<u8> = bincode::serialize(block).unwrap();
-        self.db.insert(key.as_bytes(), encoded).unwrap();
-        self.db.insert("chain:height", &block.index.to_be_bytes()).unwrap();
-        self.db.insert("chain:last_hash", block.hash.as_bytes()).unwrap();
-        
-        // Tự động tăng stats khi lưu block
-        let mut stats = self.load_stats();
-        stats.total_blocks += 1;
-        self.save_stats(&stats);
+        self.db.insert(format!("blk:{}", block.hash).as_bytes(), encoded).unwrap();
+        self.db.insert(format!("work:{}", block.hash).as_bytes(), &total_work.to_be_bytes()).unwrap();
+    }
+
+    fn get_block_by_hash(&self, hash: &str) -> Option<Block> {
+        let key = format!("blk:{}", hash);
+        self.db.get(key.as_bytes()).unwrap().and_then(|ivec| read(key.as_bytes(), &ivec))
+    }
+
+    fn get_total_work(&self, hash: &str) -> Option<f64> {
+        self.db.get(format!("work:{}", hash).as_bytes()).unwrap().map(|ivec| {
+            let bytes: [u8; 8] = ivec.as_ref().try_into().unwrap();
+            f64::from_be_bytes(bytes)
+        })
+    }
+
+    fn get_canonical_height(&self, hash: &str) -> Option<u64> {
+        self.hash_index.get(hash.as_bytes()).unwrap().map(|v| u64::from_be_bytes(v.as_ref().try_into().unwrap()))
+    }
+
+    fn get_tx_location(&self, id: &str) -> Option<TxLocation> {
+        self.tx_index.get(id.as_bytes()).unwrap().and_then(|v| read(id.as_bytes(), &v))
     }
 
-    pub fn get_height(&self) -> u64 {
+    fn get_address_txs(&self, address: &str, offset: usize, limit: usize) -> (usize, Vec<(TxLocation, Transaction)>) {
+        let prefix = format!("{}:", address);
+        let total = self.address_index.scan_prefix(prefix.as_bytes()).count();
+        let page = self.address_index.scan_prefix(prefix.as_bytes()).rev().filter_map(|r| r.ok())
+            .skip(offset)
+            .take(limit)
+            .filter_map(|(_, id)| {
+                let location = self.get_tx_location(str::from_utf8(&id).ok()?)?;
+                let tx = self.get_block(location.height)?.transactions.get(location.position as usize)?.clone();
+                Some((location, tx))
+            })
+            .collect();
+        (total, page)
+    }
+
+    fn get_height(&self) -> u64 {
         match self.db.get("chain:height").unwrap() {
             Some(ivec) => {
                 let bytes: [u8; 8] = ivec.as_ref().try_into().unwrap();
@@ -80,39 +460,132 @@ impl Storage {
         }
     }
 
-    pub fn get_last_hash(&self) -> String {
+    fn get_last_hash(&self) -> String {
         match self.db.get("chain:last_hash").unwrap() {
             Some(ivec) => str::from_utf8(&ivec).unwrap().to_string(),
-            None => "pappap-genesis-vn-2025".to_string()
+            None => String::new()
         }
     }
 
-    pub fn get_recent_blocks(&self, limit: u64) -> Vec<Block> {
-        let height = self.get_height();
-        let start = height.saturating_sub(limit);
-        let mut blocks = Vec::new();
-        for i in (start..=height).rev() {
-            let key = format!("block:{}", i);
-            if let Ok(Some(ivec)) = self.db.get(key.as_bytes()) {
-                if let Ok(block) = bincode::deserialize::<Block>(&ivec) {
-                    blocks.push(block);
+    fn get_pruned_height(&self) -> u64 {
+        self.db.get("chain:pruned_height").unwrap().map(|v| u64::from_be_bytes(v.as_ref().try_into().unwrap())).unwrap_or(0)
+    }
+
+    fn get_undo(&self, hash: &str) -> Option<StateUndo> {
+        let key = format!("undo:{}", hash);
+        self.db.get(key.as_bytes()).unwrap().and_then(|ivec| read(key.as_bytes(), &ivec))
+    }
+
+    // Mỗi block một transaction để không giữ khóa lâu trên DB lớn
+    fn prune_below(&self, keep_from: u64) -> u64 {
+        let mut pruned = 0;
+        for i in (self.get_pruned_height() + 1)..keep_from.min(self.get_height()) {
+            let block = self.get_block(i);
+            (&*self.db, &self.hash_index, &self.tx_index, &self.address_index).transaction(|(db, hashes, txs, addrs)| {
+                if let Some(b) = &block {
+                    for key in [format!("block:{}", i), format!("blk:{}", b.hash), format!("work:{}", b.hash), format!("undo:{}", b.hash), format!("qc:{}", i)] {
+                        db.remove(key.as_bytes())?;
+                    }
+                    Self::index_block(b, hashes, txs, addrs, false)?;
                 }
+                db.insert("chain:pruned_height", &i.to_be_bytes())?;
+                Ok(())
+            }).expect("Không prune được block");
+            pruned += 1;
+        }
+        // Nhánh phụ rẽ trong vùng đã prune không còn reorg tới được (xem `PappapChain::reorganize`)
+        let pruned_height = self.get_pruned_height();
+        for (key, value) in self.db.scan_prefix("blk:").filter_map(|r| r.ok()) {
+            if read::<Block>(&key, &value).is_some_and(|b| b.index > 0 && b.index <= pruned_height) {
+                self.forget_block(str::from_utf8(&key[4..]).unwrap());
             }
         }
-        blocks
+        pruned
     }
 
-    // --- AI MEMORY ---
-    pub fn learn_fact(&self, question: &str, answer: &str) {
-        let key = format!("ai:mem:{}", question.to_lowercase());
-        self.db.insert(key.as_bytes(), answer.as_bytes()).unwrap();
+
+    fn restore_snapshot(&self, genesis: &Block, snap: &Snapshot) -> Result<(), String> {
+        if self.get_block(0).is_some() || self.get_height() > 0 {
+            return Err("DB đã có dữ liệu chuỗi, chỉ nạp snapshot vào pappap_data trống".to_string());
+        }
+        let mut summary = self.summary.lock().unwrap();
+        *summary = None;
+        let tip = &snap.block;
+        (&*self.db, &self.hash_index, &self.tx_index, &self.address_index).transaction(|(db, hashes, txs, addrs)| {
+            for (block, work) in [(genesis, 0.0f64), (tip, snap.total_work)] {
+                let encoded = bincode::serialize(block).unwrap();
+                db.insert(format!("block:{}", block.index).as_bytes(), encoded.as_slice())?;
+                db.insert(format!("blk:{}", block.hash).as_bytes(), encoded)?;
+                db.insert(format!("work:{}", block.hash).as_bytes(), &work.to_be_bytes())?;
+                Self::index_block(block, hashes, txs, addrs, true)?;
+            }
+            for (addr, acct) in &snap.accounts { db.insert(format!("acct:{}", addr).as_bytes(), bincode::serialize(acct).unwrap())?; }
+            for (key, value) in &snap.data { Self::write_state_data(db, key, &Some(value.clone()))?; }
+            if let Some(qc) = &snap.qc { db.insert(format!("qc:{}", qc.height).as_bytes(), bincode::serialize(qc).unwrap())?; }
+            db.insert("chain:finalized", bincode::serialize(&(tip.index, &tip.hash)).unwrap())?;
+            db.insert("chain:pruned_height", &tip.index.saturating_sub(1).to_be_bytes())?;
+            Self::write_tip(db, tip.index, &tip.hash)
+        }).map_err(|e| format!("Không ghi được snapshot: {:?}", e))?;
+        self.db.flush().unwrap();
+        Ok(())
+    }
+
+    fn forget_block(&self, hash: &str) {
+        self.db.remove(format!("blk:{}", hash).as_bytes()).unwrap();
+        self.db.remove(format!("work:{}", hash).as_bytes()).unwrap();
     }
 
-    pub fn recall_fact(&self, question: &str) -> Option<String> {
-        let key = format!("ai:mem:{}", question.to_lowercase());
-        match self.db.get(key.as_bytes()).unwrap() {
-            Some(ivec) => Some(str::from_utf8(&ivec).ok()?.to_string()),
-            None => None
+    fn get_account(&self, address: &str) -> Option<Account> {
+        let key = format!("acct:{}", address);
+        self.db.get(key.as_bytes()).unwrap().and_then(|ivec| read(key.as_bytes(), &ivec))
+    }
+
+    fn load_accounts(&self) -> Vec<(String, Account)> {
+        self.db.scan_prefix("acct:").filter_map(|r| r.ok()).filter_map(|(k, v)| {
+            let addr = str::from_utf8(&k[5..]).ok()?.to_string();
+            Some((addr, read(&k, &v)?))
+        }).collect()
+    }
+
+    fn get_state_data(&self, key: &str) -> Option<Vec<u8>> {
+        self.db.get(format!("sd:{}", key).as_bytes()).unwrap().map(|ivec| ivec.to_vec())
+    }
+
+    fn state_summary(&self) -> Arc<StateSummary> {
+        let mut summary = self.summary.lock().unwrap();
+        let tip = self.get_last_hash();
+        match &*summary {
+            Some(s) if s.tip == tip => s.clone(),
+            _ => summary.insert(Arc::new(StateSummary::build(&tip, self.load_accounts(), self.scan_state_data("")))).clone(),
         }
     }
+
+    fn scan_state_data(&self, prefix: &str) -> Vec<(String, Vec<u8>)> {
+        self.db.scan_prefix(format!("sd:{}", prefix).as_bytes()).filter_map(|r| r.ok()).filter_map(|(k, v)| {
+            Some((str::from_utf8(&k[3..]).ok()?.to_string(), v.to_vec()))
+        }).collect()
+    }
+
+    fn save_mempool_tx(&self, tx: &Transaction, received_at: u64) {
+        let encoded = bincode::serialize(&(tx, received_at)).unwrap();
+        self.db.insert(format!("mempool:{}", tx.id).as_bytes(), encoded).unwrap();
+    }
+
+    fn remove_mempool_tx(&self, id: &str) {
+        self.db.remove(format!("mempool:{}", id).as_bytes()).unwrap();
+    }
+
+    fn load_mempool(&self) -> Vec<(Transaction, u64)> {
+        self.db.scan_prefix("mempool:").filter_map(|r| r.ok())
+            .filter_map(|(k, v)| read(&k, &v))
+            .collect()
+    }
+
+    /// Tri thức trên chuỗi trước; không có thì đọc tri thức cục bộ mà bản gốc ghi thẳng vào `ai:mem:` (ngoài state,
+    /// cùng khóa `knowledge_key`), chỉ dùng để trả lời, không vào state root.
+    fn recall_fact(&self, question: &str) -> Option<String> {
+        self.get_state_data(&knowledge_key(question))
+            .or_else(|| self.db.get(knowledge_key(question).as_bytes()).unwrap().map(|ivec| ivec.to_vec()))
+            .and_then(|v| String::from_utf8(v).ok())
+    }
 }
@@ -0,0 +1,314 @@
+use crate::block::{Block, BlockHeader};
+use crate::chain::PappapChain;
+use libp2p::PeerId;
+use serde::{Serialize, Deserialize};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+const HEADER_BATCH: u32 = 128;
+// Không thấy peer nào trong khoảng này thì coi như node đầu tiên của mạng và bắt đầu đào
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SyncRequest {
+    Status,
+    GetHeaders { from_height: u64, limit: u32 },
+    GetBlock { hash: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SyncResponse {
+    /// `pruned_height`: peer chỉ còn block chuỗi chính từ `pruned_height + 1` (0 = giữ toàn bộ lịch sử).
+    Status { genesis_hash: String, height: u64, tip_hash: String, total_work: f64, #[serde(default)] pruned_height: u64 },
+    Headers(Vec<BlockHeader>),
+    Block(Option<Block>),
+}
+
+#[derive(Debug, Clone)]
+pub enum SyncState {
+    Discovering { since: Instant },
+    Downloading { peer: PeerId, target_height: u64 },
+    /// Chuỗi tốt hơn chỉ có ở peer đã prune phần lịch sử node này còn thiếu: không đào cho tới khi
+    /// một peer lưu đủ lịch sử xuất hiện hoặc nạp snapshot (`import-snapshot`).
+    NeedsSnapshot { peer: PeerId, pruned_height: u64 },
+    Synced,
+}
+
+struct SyncInner {
+    state: SyncState,
+    next_from: u64,
+    // Height thấp nhất peer đang tải còn giữ block (sau phần đã prune)
+    floor: u64,
+    pending_blocks: HashSet<String>,
+}
+
+impl SyncInner {
+    fn is_downloading_from(&self, peer: PeerId) -> bool {
+        matches!(self.state, SyncState::Downloading { peer: p, .. } if p == peer)
+    }
+
+    fn next_batch(&self) -> SyncRequest {
+        SyncRequest::GetHeaders { from_height: self.next_from, limit: HEADER_BATCH }
+    }
+
+    /// Peer báo trạng thái; `ahead`: peer có tổng công lớn hơn và đỉnh của nó chưa có ở node này.
+    fn on_status(&mut self, peer: PeerId, height: u64, pruned_height: u64, our_height: u64, ahead: bool) -> Option<SyncRequest> {
+        if matches!(self.state, SyncState::Downloading { .. }) { return None; }
+        if !ahead {
+            if matches!(self.state, SyncState::Discovering { .. }) { self.state = SyncState::Synced; }
+            return None;
+        }
+        // Peer không còn block ngay sau đỉnh của mình: trả header rỗng sẽ bị hiểu nhầm là đã bắt kịp rồi đào nhánh riêng
+        if pruned_height > our_height {
+            self.state = SyncState::NeedsSnapshot { peer, pruned_height };
+            return None;
+        }
+        self.state = SyncState::Downloading { peer, target_height: height };
+        self.next_from = our_height + 1;
+        self.floor = pruned_height + 1;
+        self.pending_blocks.clear();
+        Some(self.next_batch())
+    }
+
+    /// Nhận một lô header; `have` cho biết block đã có trong kho. Trả về các yêu cầu cần gửi tiếp.
+    fn on_headers(&mut self, peer: PeerId, headers: &[BlockHeader], have: impl Fn(&str) -> bool) -> Vec<SyncRequest> {
+        let target_height = match self.state {
+            SyncState::Downloading { peer: p, target_height } if p == peer => target_height,
+            _ => return Vec::new(),
+        };
+        let first = match headers.first() {
+            Some(h) => h,
+            None if self.next_from > target_height => { self.state = SyncState::Synced; return Vec::new(); }
+            // Peer không trả block nó vừa quảng bá: bỏ peer này, chờ peer khác thay vì coi là đã xong
+            None => { self.abandon(); return Vec::new(); }
+        };
+        // Cha của header đầu chưa có => đang ở nhánh khác, lùi lại tìm tổ tiên chung trong phần peer còn giữ
+        if !have(&first.prev_hash) && first.index > 1 {
+            if self.next_from <= self.floor {
+                self.abandon();
+                return Vec::new();
+            }
+            self.next_from = self.next_from.saturating_sub(HEADER_BATCH as u64).max(self.floor);
+            return vec![self.next_batch()];
+        }
+        let mut requests = Vec::new();
+        for h in headers {
+            if !have(&h.hash) && self.pending_blocks.insert(h.hash.clone()) {
+                requests.push(SyncRequest::GetBlock { hash: h.hash.clone() });
+            }
+        }
+        self.next_from = headers.last().unwrap().index + 1;
+        if self.pending_blocks.is_empty() { requests.push(self.next_batch()); }
+        requests
+    }
+
+    /// Một block đã tải về (đã import hoặc bị từ chối): xin lô header kế tiếp khi hết block đang chờ.
+    fn on_block(&mut self, peer: PeerId, hash: &str) -> Option<SyncRequest> {
+        if !self.is_downloading_from(peer) { return None; }
+        self.pending_blocks.remove(hash);
+        self.pending_blocks.is_empty().then(|| self.next_batch())
+    }
+
+    fn abandon(&mut self) {
+        self.state = SyncState::Discovering { since: Instant::now() };
+        self.pending_blocks.clear();
+    }
+}
+
+/// Máy trạng thái tải lịch sử chuỗi (initial block download) từ một peer có tổng công lớn hơn.
+pub struct SyncManager {
+    inner: Mutex<SyncInner>,
+}
+
+impl SyncManager {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(SyncInner {
+                state: SyncState::Discovering { since: Instant::now() },
+                next_from: 1,
+                floor: 1,
+                pending_blocks: HashSet::new(),
+            }),
+        }
+    }
+
+    /// Chỉ cho phép đào khi đã bắt kịp mạng (hoặc hết thời gian chờ peer).
+    pub async fn is_synced(&self) -> bool {
+        let mut inner = self.inner.lock().await;
+        match inner.state {
+            SyncState::Synced => true,
+            SyncState::Discovering { since } if since.elapsed() >= DISCOVERY_TIMEOUT => {
+                inner.state = SyncState::Synced;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub async fn state(&self) -> SyncState {
+        self.inner.lock().await.state.clone()
+    }
+
+    /// Hỏi trạng thái peer (khi mới kết nối hoặc khi nhận block mồ côi từ peer đó).
+    pub async fn probe(&self, chain: &PappapChain, peer: PeerId) {
+        if matches!(self.inner.lock().await.state, SyncState::Downloading { .. }) { return; }
+        chain.p2p.send_sync_request(peer, SyncRequest::Status);
+    }
+
+    pub async fn on_response(&self, chain: &PappapChain, peer: PeerId, response: SyncResponse) {
+        match response {
+            SyncResponse::Status { genesis_hash, height, tip_hash, total_work, pruned_height } => {
+                if genesis_hash != chain.genesis_hash {
+                    println!("\n?? SYNC: Bỏ qua {} (khác genesis {})", peer, genesis_hash);
+                    return;
+                }
+                let our_height = *chain.height.read().await;
+                let our_work = chain.storage.get_total_work(&chain.last_hash.read().await).unwrap_or(0.0);
+                let ahead = total_work > our_work && chain.storage.get_block_by_hash(&tip_hash).is_none();
+                let mut inner = self.inner.lock().await;
+                if let Some(request) = inner.on_status(peer, height, pruned_height, our_height, ahead) {
+                    println!("\n?? SYNC: Tải chuỗi từ {} (height {} -> {})", peer, our_height, height);
+                    chain.p2p.send_sync_request(peer, request);
+                } else if matches!(inner.state, SyncState::NeedsSnapshot { peer: p, .. } if p == peer) {
+                    println!("\n?? SYNC: {} đã prune tới #{} (mình ở #{}), chờ peer lưu đủ lịch sử hoặc nạp snapshot bằng import-snapshot", peer, pruned_height, our_height);
+                }
+            }
+            SyncResponse::Headers(headers) => {
+                let mut inner = self.inner.lock().await;
+                if !inner.is_downloading_from(peer) { return; }
+                for request in inner.on_headers(peer, &headers, |hash| chain.storage.get_block_by_hash(hash).is_some()) {
+                    chain.p2p.send_sync_request(peer, request);
+                }
+                self.report(&inner, chain, peer).await;
+            }
+            SyncResponse::Block(block) => {
+                let mut inner = self.inner.lock().await;
+                if !inner.is_downloading_from(peer) { return; }
+                let block = match block {
+                    Some(b) => b,
+                    None => {
+                        inner.abandon();
+                        self.report(&inner, chain, peer).await;
+                        return;
+                    }
+                };
+                // Nhả khóa trước khi import: import_block có thể gọi lại is_synced (finality) trên cùng mutex
+                drop(inner);
+                let (index, hash) = (block.index, block.hash.clone());
+                if let Err(e) = chain.import_block(block).await {
+                    println!("\n?? SYNC: Block #{} bị từ chối: {}", index, e);
+                }
+                if let Some(request) = self.inner.lock().await.on_block(peer, &hash) {
+                    chain.p2p.send_sync_request(peer, request);
+                }
+            }
+        }
+    }
+
+    /// Peer đang tải bị lỗi/ngắt kết nối: bỏ phiên tải, chờ peer khác (hoặc hết thời gian chờ thì đào trên chuỗi hiện có).
+    pub async fn on_failure(&self, peer: PeerId) {
+        let mut inner = self.inner.lock().await;
+        if inner.is_downloading_from(peer) {
+            println!("\n?? SYNC: Mất kết nối với {}", peer);
+            inner.abandon();
+        }
+    }
+
+    async fn report(&self, inner: &SyncInner, chain: &PappapChain, peer: PeerId) {
+        match inner.state {
+            SyncState::Synced => println!("\n?? SYNC: Hoàn tất tại height {}", *chain.height.read().await),
+            SyncState::Discovering { .. } => println!("\n?? SYNC: Bỏ phiên tải từ {}: peer không cung cấp được phần chuỗi còn thiếu", peer),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(index: u64, prev_hash: &str) -> BlockHeader {
+        BlockHeader { index, hash: format!("h{}", index), prev_hash: prev_hash.to_string(), tx_root: String::new(), spike_score: 0.0 }
+    }
+
+    // Chuỗi h0 <- h1 <- ... <- h{n}
+    fn chain_headers(from: u64, to: u64) -> Vec<BlockHeader> {
+        (from..=to).map(|i| header(i, &format!("h{}", i - 1))).collect()
+    }
+
+    fn fresh() -> SyncInner {
+        SyncInner { state: SyncState::Discovering { since: Instant::now() }, next_from: 1, floor: 1, pending_blocks: HashSet::new() }
+    }
+
+    #[test]
+    fn downloads_batches_until_target_then_synced() {
+        let peer = PeerId::random();
+        let mut sync = fresh();
+        assert!(matches!(sync.on_status(peer, 3, 0, 0, true), Some(SyncRequest::GetHeaders { from_height: 1, .. })));
+
+        let have = |hash: &str| hash == "h0";
+        let requests = sync.on_headers(peer, &chain_headers(1, 3), have);
+        assert_eq!(requests.len(), 3);
+        assert!(sync.on_block(peer, "h1").is_none() && sync.on_block(peer, "h2").is_none());
+        assert!(matches!(sync.on_block(peer, "h3"), Some(SyncRequest::GetHeaders { from_height: 4, .. })));
+
+        // Hết header sau đỉnh đã quảng bá => bắt kịp
+        assert!(sync.on_headers(peer, &[], have).is_empty());
+        assert!(matches!(sync.state, SyncState::Synced));
+    }
+
+    #[test]
+    fn peer_not_ahead_ends_discovery_and_other_peers_are_ignored() {
+        let (peer, other) = (PeerId::random(), PeerId::random());
+        let mut sync = fresh();
+        assert!(sync.on_status(peer, 0, 0, 0, false).is_none());
+        assert!(matches!(sync.state, SyncState::Synced));
+
+        sync.on_status(peer, 5, 0, 0, true);
+        assert!(sync.on_status(other, 9, 0, 0, true).is_none());
+        assert!(sync.on_headers(other, &chain_headers(1, 2), |_| false).is_empty());
+        assert!(sync.on_block(other, "h1").is_none());
+        assert!(sync.is_downloading_from(peer));
+    }
+
+    #[test]
+    fn pruned_peer_is_not_used_for_initial_sync() {
+        let (pruned, full) = (PeerId::random(), PeerId::random());
+        let mut sync = fresh();
+        assert!(sync.on_status(pruned, 500, 300, 0, true).is_none());
+        assert!(matches!(sync.state, SyncState::NeedsSnapshot { pruned_height: 300, .. }));
+
+        // Đã có tới #300 thì peer đó vẫn trả được phần còn thiếu; peer đủ lịch sử luôn dùng được
+        assert!(matches!(sync.on_status(pruned, 500, 300, 300, true), Some(SyncRequest::GetHeaders { from_height: 301, .. })));
+        let mut sync = fresh();
+        sync.on_status(pruned, 500, 300, 0, true);
+        assert!(sync.on_status(full, 500, 0, 0, true).is_some());
+        assert!(sync.is_downloading_from(full));
+    }
+
+    #[test]
+    fn empty_headers_before_target_abandon_instead_of_finishing() {
+        let peer = PeerId::random();
+        let mut sync = fresh();
+        sync.on_status(peer, 10, 0, 0, true);
+        assert!(sync.on_headers(peer, &[], |_| false).is_empty());
+        assert!(matches!(sync.state, SyncState::Discovering { .. }));
+    }
+
+    #[test]
+    fn fork_backtracks_to_common_ancestor_within_peer_history() {
+        let peer = PeerId::random();
+        let mut sync = fresh();
+        sync.on_status(peer, 400, 0, 300, true);
+        // Header #301 nối vào block mình không có => lùi một lô
+        assert!(matches!(sync.on_headers(peer, &chain_headers(301, 302), |_| false)[..], [SyncRequest::GetHeaders { from_height: 173, .. }]));
+
+        // Peer đã prune tới #250: không lùi dưới #251, hết chỗ lùi thì bỏ peer
+        let mut sync = fresh();
+        sync.on_status(peer, 400, 250, 300, true);
+        assert!(matches!(sync.on_headers(peer, &chain_headers(301, 302), |_| false)[..], [SyncRequest::GetHeaders { from_height: 251, .. }]));
+        assert!(sync.on_headers(peer, &chain_headers(251, 252), |_| false).is_empty());
+        assert!(matches!(sync.state, SyncState::Discovering { .. }));
+    }
+}
@@ -1,73 +1,589 @@
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap, BinaryHeap};
+use std::cmp::Reverse;
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
 use ed25519_dalek::{Verifier, VerifyingKey, Signature};
 use sha2::{Sha256, Digest};
+use crate::wallet::address_from_pubkey;
+use crate::state::Account;
+use crate::storage::ChainStore;
+use crate::ethics::EthicsFilter;
+use crate::params::ParamChange;
+use crate::governance::VoteChoice;
+use crate::wallet::is_valid_address;
+use crate::webnode::MAX_PROOFS_PER_EPOCH;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Phí tối thiểu lúc genesis; sau đó do governance quyết định (`ChainParams::min_fee`).
+pub const MIN_FEE: u64 = 10;
+/// Nonce được phép đi trước nonce hiện tại của tài khoản tối đa bao nhiêu.
+pub const MAX_NONCE_GAP: u64 = 64;
+/// Thay giao dịch cùng sender + nonce cần phí cao hơn ít nhất 10%.
+pub const RBF_BUMP_PERCENT: u64 = 10;
+pub const DEFAULT_MEMPOOL_MAX: usize = 10_000;
+pub const DEFAULT_MEMPOOL_TTL_SECS: u64 = 3600;
+/// `sender` của giao dịch coinbase (không phải public key nên mempool/peer không thể gửi).
+pub const COINBASE_SENDER: &str = "COINBASE";
+
+/// Lý do mempool từ chối giao dịch (trả nguyên về cho API).
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "reason")]
+pub enum TxReject {
+    InvalidId { expected: String },
+    InvalidSender,
+    InvalidSignature,
+    InvalidPayload { error: String },
+    FeeTooLow { min_fee: u64 },
+    NonceTooLow { expected: u64 },
+    NonceTooHigh { max: u64 },
+    InsufficientBalance { balance: u64, required: u64 },
+    InsufficientStake { staked: u64, required: u64 },
+    GovernanceRejected { error: String },
+    WorkRejected { error: String },
+    ReplacementUnderpriced { min_fee: u64 },
+    PoolFull { min_fee: u64 },
+    Duplicate,
+}
+
+impl TxReject {
+    /// Giao dịch sai bất kể trạng thái chuỗi (peer relay loại này sẽ bị phạt).
+    /// Phí thấp không tính vì phí tối thiểu đổi theo governance, peer có thể chưa tới height kích hoạt.
+    pub fn is_invalid(&self) -> bool {
+        matches!(self, TxReject::InvalidId { .. } | TxReject::InvalidSender | TxReject::InvalidSignature | TxReject::InvalidPayload { .. })
+    }
+}
+
+/// Giao dịch phiên bản 1: chỉ chuyển khoản, ký theo chuỗi cũ `sender:receiver:amount:fee:nonce:timestamp`.
+pub const TX_VERSION_LEGACY: u8 = 1;
+/// Phiên bản hiện tại: payload có kiểu, ký theo `TxPayload::canonical`.
+pub const TX_VERSION: u8 = 2;
+const MAX_TITLE_LEN: usize = 120;
+const MAX_TEXT_LEN: usize = 4000;
+
+fn text_digest(text: &str) -> String {
+    hex::encode(Sha256::digest(text.as_bytes()))
+}
+
+/// Nội dung giao dịch. Chỉ được thêm biến thể mới vào CUỐI enum (bincode mã hóa theo thứ tự biến thể),
+/// kèm một `TX_VERSION` mới nếu cách ký thay đổi.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TxPayload {
+    Transfer { receiver: String, amount: u64 },
+    Stake { amount: u64 },
+    Unstake { amount: u64 },
+    Proposal { title: String, description: String },
+    Vote { proposal_id: u64, approve: bool },
+    Knowledge { keyword: String, answer: String },
+    /// Chỉ producer tạo, đứng đầu block (xem `Transaction::coinbase`).
+    Coinbase { receiver: String, amount: u64 },
+    /// Đề xuất kèm thay đổi tham số chuỗi, tự áp dụng nếu được thông qua.
+    ParamProposal { title: String, description: String, change: ParamChange },
+    /// Bỏ phiếu có phiếu trắng; bỏ lại cho cùng đề xuất trước hạn để đổi phiếu.
+    CastVote { proposal_id: u64, choice: VoteChoice },
+    /// Ủy quyền biểu quyết (theo stake) cho một địa chỉ khác.
+    Delegate { delegate: String },
+    Undelegate,
+    /// Bằng chứng công của web worker (xem `webnode::record_work`): lời giải `snn_subtask` cho seed
+    /// `webwork_seed(epoch, worker, anchor, index)`, ghi đóng góp cho `worker`; người gửi (worker hoặc ví chuyển tiếp) trả phí.
+    WebWork { epoch: u64, worker: String, index: u32, answer: u64 },
+}
+
+impl TxPayload {
+    /// Chuỗi đại diện đem ký; trường văn bản tự do được băm để không lẫn dấu phân cách.
+    pub fn canonical(&self) -> String {
+        match self {
+            TxPayload::Transfer { receiver, amount } => format!("transfer:{}:{}", receiver, amount),
+            TxPayload::Stake { amount } => format!("stake:{}", amount),
+            TxPayload::Unstake { amount } => format!("unstake:{}", amount),
+            TxPayload::Proposal { title, description } => format!("proposal:{}:{}", text_digest(title), text_digest(description)),
+            TxPayload::Vote { proposal_id, approve } => format!("vote:{}:{}", proposal_id, approve),
+            TxPayload::Knowledge { keyword, answer } => format!("knowledge:{}:{}", text_digest(keyword), text_digest(answer)),
+            TxPayload::Coinbase { receiver, amount } => format!("coinbase:{}:{}", receiver, amount),
+            TxPayload::ParamProposal { title, description, change } => {
+                format!("param_proposal:{}:{}:{}", text_digest(title), text_digest(description), change.canonical())
+            }
+            TxPayload::CastVote { proposal_id, choice } => format!("cast_vote:{}:{:?}", proposal_id, choice),
+            TxPayload::Delegate { delegate } => format!("delegate:{}", delegate),
+            TxPayload::Undelegate => "undelegate".to_string(),
+            TxPayload::WebWork { epoch, worker, index, answer } => format!("webwork:{}:{}:{}:{}", epoch, worker, index, answer),
+        }
+    }
+
+    /// Số dư rời tài khoản người gửi ngoài phí.
+    pub fn spend(&self) -> u64 {
+        match self {
+            TxPayload::Transfer { amount, .. } | TxPayload::Stake { amount } => *amount,
+            _ => 0,
+        }
+    }
+
+    /// Các trường văn bản tự do (phải qua bộ lọc nội dung).
+    pub fn texts(&self) -> Vec<&str> {
+        match self {
+            TxPayload::Proposal { title, description } | TxPayload::ParamProposal { title, description, .. } => vec![title, description],
+            TxPayload::Knowledge { keyword, answer } => vec![keyword, answer],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Kiểm tra không phụ thuộc trạng thái chuỗi.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            TxPayload::Transfer { receiver, amount } => {
+                if receiver.is_empty() { return Err("Thiếu người nhận".to_string()); }
+                if *amount == 0 { return Err("Số lượng phải lớn hơn 0".to_string()); }
+            }
+            TxPayload::Stake { amount } | TxPayload::Unstake { amount } => {
+                if *amount == 0 { return Err("Số lượng phải lớn hơn 0".to_string()); }
+            }
+            TxPayload::Proposal { title, description } | TxPayload::ParamProposal { title, description, .. } => {
+                if title.trim().is_empty() || title.chars().count() > MAX_TITLE_LEN {
+                    return Err(format!("Tiêu đề phải có 1-{} ký tự", MAX_TITLE_LEN));
+                }
+                if description.chars().count() > MAX_TEXT_LEN { return Err("Mô tả quá dài".to_string()); }
+                EthicsFilter::check(title)?;
+                EthicsFilter::check(description)?;
+                if let TxPayload::ParamProposal { change, .. } = self { change.validate()?; }
+            }
+            TxPayload::Vote { .. } | TxPayload::CastVote { .. } | TxPayload::Undelegate => {}
+            TxPayload::Delegate { delegate } => {
+                if !is_valid_address(delegate) { return Err("Địa chỉ được ủy quyền không hợp lệ".to_string()); }
+            }
+            TxPayload::Knowledge { keyword, answer } => {
+                if keyword.trim().is_empty() || keyword.chars().count() > MAX_TITLE_LEN {
+                    return Err(format!("Từ khóa phải có 1-{} ký tự", MAX_TITLE_LEN));
+                }
+                if answer.trim().is_empty() || answer.chars().count() > MAX_TEXT_LEN {
+                    return Err(format!("Nội dung phải có 1-{} ký tự", MAX_TEXT_LEN));
+                }
+                EthicsFilter::check(keyword)?;
+                EthicsFilter::check(answer)?;
+            }
+            TxPayload::WebWork { worker, index, .. } => {
+                if !is_valid_address(worker) { return Err("Địa chỉ web worker không hợp lệ".to_string()); }
+                if *index >= MAX_PROOFS_PER_EPOCH { return Err(format!("Chỉ số bằng chứng phải nhỏ hơn {}", MAX_PROOFS_PER_EPOCH)); }
+            }
+            TxPayload::Coinbase { .. } => return Err("Coinbase không được gửi như giao dịch thường".to_string()),
+        }
+        Ok(())
+    }
+}
+
+/// Phong bì giao dịch đã ký: người gửi, nonce, phí và một payload có kiểu.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     pub id: String,
+    pub version: u8,
     pub sender: String,
-    pub receiver: String,
-    pub amount: u64,
-    pub fee: u64,
     pub nonce: u64,
+    pub fee: u64,
     pub timestamp: i64,
+    pub payload: TxPayload,
     pub signature: String,
 }
 
 impl Transaction {
+    fn signing_payload(&self) -> String {
+        match (&self.payload, self.version) {
+            (TxPayload::Transfer { receiver, amount }, TX_VERSION_LEGACY) => format!(
+                "{}:{}:{}:{}:{}:{}",
+                self.sender, receiver, amount, self.fee, self.nonce, self.timestamp
+            ),
+            _ => format!(
+                "v{}:{}:{}:{}:{}:{}",
+                self.version, self.sender, self.fee, self.nonce, self.timestamp, self.payload.canonical()
+            ),
+        }
+    }
+
     pub fn calculate_hash(&self) -> String {
-        let payload = format!(
-            "{}:{}:{}:{}:{}:{}",
-            self.sender, self.receiver, self.amount, self.fee, self.nonce, self.timestamp
-        );
         let mut hasher = Sha256::new();
-        hasher.update(payload);
+        hasher.update(self.signing_payload());
         hex::encode(hasher.finalize())
     }
 
+    /// Phiên bản được hỗ trợ và payload hợp lệ (giao dịch thường, không phải coinbase).
+    pub fn check_payload(&self) -> Result<(), String> {
+        match self.version {
+            TX_VERSION_LEGACY if !matches!(self.payload, TxPayload::Transfer { .. }) => {
+                Err("Giao dịch phiên bản 1 chỉ hỗ trợ chuyển khoản".to_string())
+            }
+            TX_VERSION_LEGACY | TX_VERSION => self.payload.validate(),
+            v => Err(format!("Phiên bản giao dịch {} không được hỗ trợ", v)),
+        }
+    }
+
+    /// Giao dịch phiên bản hiện tại ký bằng ví ed25519 (node không tự ký giao dịch, chỉ dùng trong test).
+    #[cfg(test)]
+    pub fn signed(wallet: &crate::wallet::Wallet, nonce: u64, fee: u64, payload: TxPayload) -> Self {
+        let mut tx = Self {
+            id: String::new(),
+            version: TX_VERSION,
+            sender: wallet.public_key.clone(),
+            nonce,
+            fee,
+            timestamp: now_secs() as i64,
+            payload,
+            signature: String::new(),
+        };
+        tx.signature = wallet.sign(tx.signing_payload().as_bytes());
+        tx.id = tx.calculate_hash();
+        tx
+    }
+
+    /// Giao dịch coinbase đứng đầu mỗi block: trả phần thưởng + phí cho producer, nonce = height.
+    pub fn coinbase(height: u64, receiver: &str, amount: u64, timestamp: i64) -> Self {
+        let mut tx = Self {
+            id: String::new(),
+            version: TX_VERSION,
+            sender: COINBASE_SENDER.to_string(),
+            nonce: height,
+            fee: 0,
+            timestamp,
+            payload: TxPayload::Coinbase { receiver: receiver.to_string(), amount },
+            signature: String::new(),
+        };
+        tx.id = tx.calculate_hash();
+        tx
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.sender == COINBASE_SENDER && matches!(self.payload, TxPayload::Coinbase { .. })
+    }
+
+    /// Địa chỉ ví của người gửi (trường `sender` là public key ed25519 dạng hex).
+    pub fn sender_address(&self) -> Option<String> {
+        let pub_bytes = hex::decode(&self.sender).ok()?;
+        if pub_bytes.len() != 32 { return None; }
+        Some(address_from_pubkey(&pub_bytes))
+    }
+
+    /// Các địa chỉ liên quan (người gửi, người nhận, người được ủy quyền), dùng cho chỉ mục theo địa chỉ.
+    pub fn addresses(&self) -> Vec<String> {
+        let mut addrs: Vec<String> = self.sender_address().into_iter().collect();
+        match &self.payload {
+            TxPayload::Transfer { receiver, .. } | TxPayload::Coinbase { receiver, .. } => addrs.push(receiver.clone()),
+            TxPayload::Delegate { delegate } => addrs.push(delegate.clone()),
+            TxPayload::WebWork { worker, .. } => addrs.push(worker.clone()),
+            _ => {}
+        }
+        addrs.dedup();
+        addrs
+    }
+
     pub fn verify(&self) -> bool {
-        if let Ok(pub_bytes) = hex::decode(&self.sender) {
-            if let Ok(pub_key) = VerifyingKey::from_bytes(pub_bytes.as_slice().try_into().unwrap()) {
-                if let Ok(sig_bytes) = hex::decode(&self.signature) {
-                    if sig_bytes.len() == 64 {
-                        let sig_arr: [u8; 64] = sig_bytes.try_into().unwrap();
-                        let signature = Signature::from_bytes(&sig_arr);
-                        
-                        let payload = format!(
-                            "{}:{}:{}:{}:{}:{}",
-                            self.sender, self.receiver, self.amount, self.fee, self.nonce, self.timestamp
-                        );
-                        return pub_key.verify(payload.as_bytes(), &signature).is_ok();
-                    }
-                }
+        let pub_bytes: [u8; 32] = match hex::decode(&self.sender).ok().and_then(|b| b.try_into().ok()) {
+            Some(b) => b,
+            None => return false,
+        };
+        let sig_arr: [u8; 64] = match hex::decode(&self.signature).ok().and_then(|b| b.try_into().ok()) {
+            Some(b) => b,
+            None => return false,
+        };
+        match VerifyingKey::from_bytes(&pub_bytes) {
+            Ok(pub_key) => pub_key.verify(self.signing_payload().as_bytes(), &Signature::from_bytes(&sig_arr)).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct PoolInner {
+    by_id: HashMap<String, Transaction>,
+    // địa chỉ người gửi -> nonce -> tx id (mỗi nonce chỉ một giao dịch)
+    by_sender: HashMap<String, BTreeMap<u64, String>>,
+    // tx id -> thời điểm nhận (unix giây), dùng cho TTL
+    received_at: HashMap<String, u64>,
+}
+
+impl PoolInner {
+    fn insert(&mut self, sender: String, tx: Transaction, received_at: u64) {
+        self.by_sender.entry(sender).or_default().insert(tx.nonce, tx.id.clone());
+        self.received_at.insert(tx.id.clone(), received_at);
+        self.by_id.insert(tx.id.clone(), tx);
+    }
+
+    fn remove(&mut self, id: &str) -> Option<Transaction> {
+        let tx = self.by_id.remove(id)?;
+        self.received_at.remove(id);
+        if let Some(sender) = tx.sender_address() {
+            if let Some(queue) = self.by_sender.get_mut(&sender) {
+                queue.remove(&tx.nonce);
+                if queue.is_empty() { self.by_sender.remove(&sender); }
             }
         }
-        false
+        Some(tx)
+    }
+
+    // Ứng viên bị đẩy ra khi đầy: giao dịch nonce cao nhất của mỗi người gửi, lấy cái phí thấp nhất
+    fn cheapest_tail(&self) -> Option<&Transaction> {
+        self.by_sender.values()
+            .filter_map(|queue| queue.values().next_back())
+            .filter_map(|id| self.by_id.get(id))
+            .min_by(|a, b| a.fee.cmp(&b.fee).then_with(|| b.id.cmp(&a.id)))
     }
 }
 
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+/// Mempool ưu tiên theo phí (phí cố định mỗi giao dịch), giữ thứ tự nonce của từng người gửi.
+/// Mọi thay đổi được ghi xuống kho (`ChainStore::save_mempool_tx`) để giữ lại qua các lần khởi động lại.
 #[derive(Clone)]
 pub struct Mempool {
-    pub pending: Arc<RwLock<HashMap<String, Transaction>>>,
+    inner: Arc<RwLock<PoolInner>>,
+    storage: Arc<dyn ChainStore>,
+    max_size: usize,
+    ttl_secs: u64,
+    // Phí tối thiểu hiện hành trên chuỗi, chain cập nhật khi tham số đổi
+    min_fee: Arc<AtomicU64>,
 }
 
 impl Mempool {
-    pub fn new() -> Self {
-        Self { pending: Arc::new(RwLock::new(HashMap::new())) }
-    }
-    pub fn add_tx(&self, tx: Transaction) -> bool {
-        let mut pool = self.pending.write().unwrap();
-        if pool.contains_key(&tx.id) { return false; }
-        pool.insert(tx.id.clone(), tx);
-        true
-    }
-    pub fn pop_n(&self, n: usize) -> Vec<Transaction> {
-        let mut pool = self.pending.write().unwrap();
-        let keys: Vec<String> = pool.keys().take(n).cloned().collect();
-        let mut txs = Vec::new();
-        for key in keys { if let Some(tx) = pool.remove(&key) { txs.push(tx); } }
+    pub fn new(storage: Arc<dyn ChainStore>, max_size: usize, ttl_secs: u64) -> Self {
+        Self { inner: Arc::new(RwLock::new(PoolInner::default())), storage, max_size, ttl_secs, min_fee: Arc::new(AtomicU64::new(MIN_FEE)) }
+    }
+
+    /// Cấu hình từ PAPPAP_MEMPOOL_MAX (mặc định 10_000 giao dịch) và PAPPAP_MEMPOOL_TTL_SECS (mặc định 1 giờ).
+    pub fn from_env(storage: Arc<dyn ChainStore>) -> Self {
+        let max_size = std::env::var("PAPPAP_MEMPOOL_MAX").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_MEMPOOL_MAX);
+        let ttl_secs = std::env::var("PAPPAP_MEMPOOL_TTL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_MEMPOOL_TTL_SECS);
+        Self::new(storage, max_size, ttl_secs)
+    }
+
+    /// Nạp lại mempool đã lưu, kiểm tra lại từng giao dịch theo trạng thái hiện tại và bỏ giao dịch hết hạn.
+    /// Trả về (số giữ lại, số bị bỏ).
+    pub fn reload(&self, account_of: impl Fn(&str) -> Account) -> (usize, usize) {
+        let mut saved = self.storage.load_mempool();
+        for (tx, _) in &saved { self.storage.remove_mempool_tx(&tx.id); }
+        saved.sort_by_key(|(tx, _)| tx.nonce);
+
+        let now = now_secs();
+        let mut kept = 0;
+        for (tx, received_at) in saved.iter().cloned() {
+            if now.saturating_sub(received_at) >= self.ttl_secs { continue; }
+            let account = match tx.sender_address() { Some(addr) => account_of(&addr), None => continue };
+            if self.insert_checked(tx, &account, received_at).is_ok() { kept += 1; }
+        }
+        (kept, saved.len() - kept)
+    }
+
+    /// Bỏ các giao dịch đã nằm trong mempool lâu hơn TTL. Trả về số giao dịch bị bỏ.
+    pub fn expire(&self) -> usize {
+        let now = now_secs();
+        let mut pool = self.inner.write().unwrap();
+        let expired: Vec<String> = pool.received_at.iter()
+            .filter(|(_, at)| now.saturating_sub(**at) >= self.ttl_secs)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &expired {
+            pool.remove(id);
+            self.storage.remove_mempool_tx(id);
+        }
+        expired.len()
+    }
+
+    pub fn min_fee(&self) -> u64 {
+        self.min_fee.load(Ordering::Relaxed)
+    }
+
+    pub fn set_min_fee(&self, fee: u64) {
+        self.min_fee.store(fee, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Transaction> {
+        self.inner.read().unwrap().by_id.get(id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().by_id.len()
+    }
+
+    /// Kiểm tra đầy đủ rồi nhận giao dịch; `account` là trạng thái hiện tại của người gửi trên chuỗi.
+    pub fn add_tx(&self, tx: Transaction, account: &Account) -> Result<(), TxReject> {
+        self.insert_checked(tx, account, now_secs())
+    }
+
+    fn insert_checked(&self, tx: Transaction, account: &Account, received_at: u64) -> Result<(), TxReject> {
+        let expected = tx.calculate_hash();
+        if tx.id != expected { return Err(TxReject::InvalidId { expected }); }
+        let sender = tx.sender_address().ok_or(TxReject::InvalidSender)?;
+        if !tx.verify() { return Err(TxReject::InvalidSignature); }
+        tx.check_payload().map_err(|error| TxReject::InvalidPayload { error })?;
+        let min_fee = self.min_fee();
+        if tx.fee < min_fee { return Err(TxReject::FeeTooLow { min_fee }); }
+        if tx.nonce < account.nonce { return Err(TxReject::NonceTooLow { expected: account.nonce }); }
+        if tx.nonce > account.nonce + MAX_NONCE_GAP { return Err(TxReject::NonceTooHigh { max: account.nonce + MAX_NONCE_GAP }); }
+
+        let mut pool = self.inner.write().unwrap();
+        if pool.by_id.contains_key(&tx.id) { return Err(TxReject::Duplicate); }
+
+        // Cùng sender + nonce: chỉ thay khi phí mới cao hơn ít nhất RBF_BUMP_PERCENT
+        let replaced = pool.by_sender.get(&sender).and_then(|q| q.get(&tx.nonce)).cloned();
+        if let Some(old_id) = &replaced {
+            let old_fee = pool.by_id[old_id].fee;
+            let min_fee = old_fee.saturating_add(old_fee * RBF_BUMP_PERCENT / 100).max(old_fee + 1);
+            if tx.fee < min_fee { return Err(TxReject::ReplacementUnderpriced { min_fee }); }
+        }
+
+        // Số dư phải đủ cho cả các giao dịch cùng người gửi đang chờ (trừ giao dịch bị thay)
+        let pending_spend: u64 = pool.by_sender.get(&sender).map(|q| {
+            q.values().filter(|id| Some(*id) != replaced.as_ref())
+                .map(|id| pool.by_id[id].payload.spend().saturating_add(pool.by_id[id].fee))
+                .sum()
+        }).unwrap_or(0);
+        let required = pending_spend.saturating_add(tx.payload.spend()).saturating_add(tx.fee);
+        if account.balance < required {
+            return Err(TxReject::InsufficientBalance { balance: account.balance, required });
+        }
+        if let TxPayload::Unstake { amount } = tx.payload {
+            if account.staked < amount { return Err(TxReject::InsufficientStake { staked: account.staked, required: amount }); }
+        }
+
+        if let Some(old_id) = replaced {
+            pool.remove(&old_id);
+            self.storage.remove_mempool_tx(&old_id);
+        } else if pool.by_id.len() >= self.max_size {
+            let (evict_id, evict_fee) = match pool.cheapest_tail() {
+                Some(t) => (t.id.clone(), t.fee),
+                None => return Err(TxReject::PoolFull { min_fee }),
+            };
+            if tx.fee <= evict_fee { return Err(TxReject::PoolFull { min_fee: evict_fee + 1 }); }
+            pool.remove(&evict_id);
+            self.storage.remove_mempool_tx(&evict_id);
+        }
+        self.storage.save_mempool_tx(&tx, received_at);
+        pool.insert(sender, tx, received_at);
+        Ok(())
+    }
+
+    /// Chọn tối đa `n` giao dịch phí cao trước, nhưng mỗi người gửi luôn theo thứ tự nonce liên tiếp.
+    /// Giao dịch không bị xóa ở đây; chúng rời mempool khi block chứa chúng được nối (`remove_included`).
+    pub fn select(&self, n: usize) -> Vec<Transaction> {
+        let pool = self.inner.read().unwrap();
+        let mut heap = BinaryHeap::new();
+        let mut cursors: HashMap<&str, std::collections::btree_map::Values<'_, u64, String>> = HashMap::new();
+        for (sender, queue) in &pool.by_sender {
+            let mut it = queue.values();
+            if let Some(id) = it.next() {
+                let tx = &pool.by_id[id];
+                heap.push((tx.fee, Reverse(tx.id.as_str()), sender.as_str()));
+            }
+            cursors.insert(sender.as_str(), it);
+        }
+
+        let mut txs: Vec<Transaction> = Vec::new();
+        while txs.len() < n {
+            let (_, Reverse(id), sender) = match heap.pop() { Some(e) => e, None => break };
+            let tx = &pool.by_id[id];
+            if let Some(next) = cursors.get_mut(sender).and_then(|it| it.next()) {
+                let next_tx = &pool.by_id[next];
+                if next_tx.nonce == tx.nonce + 1 {
+                    heap.push((next_tx.fee, Reverse(next_tx.id.as_str()), sender));
+                }
+            }
+            txs.push(tx.clone());
+        }
         txs
     }
+
+    /// Bỏ các giao dịch đã nằm trong block vừa nối, cùng mọi giao dịch cùng người gửi có nonce đã dùng.
+    pub fn remove_included(&self, txs: &[Transaction]) {
+        let mut pool = self.inner.write().unwrap();
+        for tx in txs {
+            let mut stale: Vec<String> = tx.sender_address()
+                .and_then(|sender| pool.by_sender.get(&sender))
+                .map(|q| q.range(..=tx.nonce).map(|(_, id)| id.clone()).collect())
+                .unwrap_or_default();
+            stale.push(tx.id.clone());
+            for id in stale {
+                if pool.remove(&id).is_some() { self.storage.remove_mempool_tx(&id); }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memstore::MemoryStore;
+    use crate::wallet::Wallet;
+
+    fn pool(max_size: usize) -> Mempool {
+        Mempool::new(Arc::new(MemoryStore::new()), max_size, DEFAULT_MEMPOOL_TTL_SECS)
+    }
+
+    fn funded() -> Account {
+        Account { balance: 1_000_000, ..Default::default() }
+    }
+
+    fn transfer(wallet: &Wallet, nonce: u64, fee: u64) -> Transaction {
+        Transaction::signed(wallet, nonce, fee, TxPayload::Transfer { receiver: "PAPPAP00000000000000000000000000000001".to_string(), amount: 1 })
+    }
+
+    #[test]
+    fn replacement_needs_full_fee_bump() {
+        let (mempool, wallet) = (pool(10), Wallet::new());
+        let original = transfer(&wallet, 0, 1_000);
+        mempool.add_tx(original.clone(), &funded()).unwrap();
+
+        // 1_000 + 10% = 1_100: thấp hơn 1 đơn vị bị từ chối, đúng bằng mức tối thiểu thì thay
+        let below = transfer(&wallet, 0, 1_099);
+        assert_eq!(mempool.add_tx(below, &funded()), Err(TxReject::ReplacementUnderpriced { min_fee: 1_100 }));
+        let bumped = transfer(&wallet, 0, 1_100);
+        mempool.add_tx(bumped.clone(), &funded()).unwrap();
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.get(&original.id).is_none());
+        assert!(mempool.get(&bumped.id).is_some());
+    }
+
+    #[test]
+    fn replacement_of_min_fee_tx_needs_at_least_one_unit() {
+        let (mempool, wallet) = (pool(10), Wallet::new());
+        mempool.add_tx(transfer(&wallet, 0, MIN_FEE), &funded()).unwrap();
+        let same_fee = Transaction::signed(&wallet, 0, MIN_FEE, TxPayload::Stake { amount: 1 });
+        assert_eq!(mempool.add_tx(same_fee, &funded()), Err(TxReject::ReplacementUnderpriced { min_fee: MIN_FEE + 1 }));
+    }
+
+    #[test]
+    fn full_pool_evicts_cheapest_tail_only_for_higher_fee() {
+        let mempool = pool(3);
+        let (a, b, c) = (Wallet::new(), Wallet::new(), Wallet::new());
+        let a0 = transfer(&a, 0, 100);
+        let a1 = transfer(&a, 1, 15);
+        let b0 = transfer(&b, 0, 50);
+        for tx in [&a0, &a1, &b0] { mempool.add_tx(tx.clone(), &funded()).unwrap(); }
+
+        // Phí bằng giao dịch rẻ nhất không đủ để đẩy nó ra
+        assert_eq!(mempool.add_tx(transfer(&c, 0, 15), &funded()), Err(TxReject::PoolFull { min_fee: 16 }));
+        let c0 = transfer(&c, 0, 16);
+        mempool.add_tx(c0.clone(), &funded()).unwrap();
+        assert_eq!(mempool.len(), 3);
+        assert!(mempool.get(&a1.id).is_none());
+        assert!(mempool.get(&a0.id).is_some());
+        assert!(mempool.get(&c0.id).is_some());
+    }
+
+    #[test]
+    fn select_orders_by_fee_but_keeps_sender_nonces() {
+        let mempool = pool(10);
+        let (a, b) = (Wallet::new(), Wallet::new());
+        let a0 = transfer(&a, 0, 10);
+        let a1 = transfer(&a, 1, 100);
+        let b0 = transfer(&b, 0, 50);
+        for tx in [&a1, &a0, &b0] { mempool.add_tx(tx.clone(), &funded()).unwrap(); }
+        let ids: Vec<String> = mempool.select(10).into_iter().map(|tx| tx.id).collect();
+        assert_eq!(ids, vec![b0.id, a0.id, a1.id]);
+    }
+
+    #[test]
+    fn pending_spend_counts_against_balance() {
+        let (mempool, wallet) = (pool(10), Wallet::new());
+        let account = Account { balance: 22, ..Default::default() };
+        mempool.add_tx(transfer(&wallet, 0, 10), &account).unwrap();
+        assert_eq!(mempool.add_tx(transfer(&wallet, 1, 11), &account), Err(TxReject::InsufficientBalance { balance: 22, required: 23 }));
+        mempool.add_tx(transfer(&wallet, 1, 10), &account).unwrap();
+    }
 }
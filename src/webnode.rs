@@ -1,6 +1,198 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
+use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::time::{SystemTime, UNIX_EPOCH};
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+use sha2::{Sha256, Digest};
+use crate::quantum::QuantumWallet;
+use crate::state::StateView;
+use crate::storage::ChainStore;
+use crate::transaction::TxPayload;
+use crate::wallet::is_valid_address;
+
+/// Số block mỗi epoch tính đóng góp của web worker (~8 phút với slot 800ms).
+pub const EPOCH_BLOCKS: u64 = 600;
+/// Phần trăm phần thưởng block chia cho web worker của epoch trước.
+pub const WEB_REWARD_PERCENT: u64 = 20;
+/// Số worker tối đa được trả trong một block (ưu tiên địa chỉ đang được nợ nhiều nhất).
+pub const MAX_PAYOUTS_PER_BLOCK: usize = 16;
+/// Số bước SNN của một bằng chứng công; mỗi bằng chứng hợp lệ được tính bấy nhiêu đơn vị đóng góp.
+pub const WEBWORK_STEPS: u32 = 20_000;
+/// Số bằng chứng tối đa của một địa chỉ trong một epoch (chỉ số bằng chứng `0..MAX_PROOFS_PER_EPOCH`),
+/// cũng là trần đóng góp được ghi cho một địa chỉ trong một epoch.
+pub const MAX_PROOFS_PER_EPOCH: u32 = 64;
+const CHALLENGE_TTL_SECS: u64 = 60;
+const SUBTASK_THRESHOLD: i64 = 2_000;
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+}
+
+pub fn epoch_of(height: u64) -> u64 {
+    height / EPOCH_BLOCKS
+}
+
+// Dữ liệu web worker trong state, theo epoch: `webwork:{epoch:020}:anchor` (hash block neo), `:units:{address}` (đóng góp),
+// `:seed:{seed}` (bằng chứng đã dùng), `:paid:{address}`, `:accrued` và `:released` (tiền trả cho epoch đó)
+fn epoch_prefix(epoch: u64) -> String {
+    format!("webwork:{:020}:", epoch)
+}
+
+fn read_u64(raw: Option<Vec<u8>>) -> u64 {
+    raw.and_then(|v| v.try_into().ok()).map(u64::from_be_bytes).unwrap_or(0)
+}
+
+fn write_u64(view: &mut StateView, key: &str, value: u64) {
+    view.set_data(key, Some(value.to_be_bytes().to_vec()));
+}
+
+fn anchor_key(epoch: u64) -> String {
+    format!("{}anchor", epoch_prefix(epoch))
+}
+
+/// Gọi đầu mỗi block, trước khi áp giao dịch: block đầu tiên của epoch ghi hash block cha (block cuối epoch trước)
+/// làm block neo của epoch. Seed bằng chứng phụ thuộc block neo nên không ai tính trước được trước khi epoch bắt đầu.
+pub fn anchor_epoch(view: &mut StateView, parent_hash: &str) {
+    let key = anchor_key(epoch_of(view.height));
+    if view.get_data(&key).is_none() {
+        view.set_data(&key, Some(parent_hash.as_bytes().to_vec()));
+    }
+}
+
+/// Hash block neo của `epoch`, `None` nếu epoch chưa có block nào trên chuỗi.
+pub fn epoch_anchor(storage: &dyn ChainStore, epoch: u64) -> Option<String> {
+    storage.get_state_data(&anchor_key(epoch)).and_then(|v| String::from_utf8(v).ok())
+}
+
+/// Seed của bằng chứng thứ `index` của `worker` trong `epoch`: ràng buộc địa chỉ nhận thưởng và block neo của epoch,
+/// nên người gửi không tự chọn được seed và mỗi địa chỉ chỉ có MAX_PROOFS_PER_EPOCH seed mỗi epoch.
+pub fn webwork_seed(epoch: u64, worker: &str, anchor: &str, index: u32) -> String {
+    hex::encode(Sha256::digest(format!("{}:{}:{}:{}", epoch, worker, anchor, index).as_bytes()))
+}
+
+fn seed_used(epoch: u64, seed: &str) -> String {
+    format!("{}seed:{}", epoch_prefix(epoch), seed)
+}
+
+/// Ghi nhận bằng chứng công của giao dịch WebWork: epoch của seed là epoch hiện tại hoặc ngay trước và đã có block neo,
+/// chỉ số trong giới hạn, seed chưa dùng và lời giải đúng. Đóng góp được cộng cho `worker` ở epoch của block chứa
+/// giao dịch, không vượt trần MAX_PROOFS_PER_EPOCH bằng chứng; trả về tổng đóng góp của `worker`.
+pub fn record_work(view: &mut StateView, epoch: u64, worker: &str, index: u32, answer: u64) -> Result<u64, String> {
+    let current = epoch_of(view.height);
+    if epoch > current || epoch + 1 < current {
+        return Err(format!("Bằng chứng công của epoch {} đã hết hạn (epoch hiện tại {})", epoch, current));
+    }
+    if index >= MAX_PROOFS_PER_EPOCH {
+        return Err(format!("Chỉ số bằng chứng {} vượt giới hạn {} mỗi epoch", index, MAX_PROOFS_PER_EPOCH));
+    }
+    let anchor = view.get_data(&anchor_key(epoch)).and_then(|v| String::from_utf8(v).ok())
+        .ok_or(format!("Epoch {} chưa có block neo", epoch))?;
+    let seed = webwork_seed(epoch, worker, &anchor, index);
+    let used = seed_used(epoch, &seed);
+    if view.get_data(&used).is_some() {
+        return Err("Bằng chứng công đã được dùng".to_string());
+    }
+    let key = format!("{}units:{}", epoch_prefix(current), worker);
+    let total = read_u64(view.get_data(&key)) + WEBWORK_STEPS as u64;
+    if total > MAX_PROOFS_PER_EPOCH as u64 * WEBWORK_STEPS as u64 {
+        return Err(format!("{} đã đạt trần đóng góp của epoch {}", worker, current));
+    }
+    if snn_subtask(&seed, WEBWORK_STEPS) != answer {
+        return Err("Sai lời giải".to_string());
+    }
+    view.set_data(&used, Some(Vec::new()));
+    write_u64(view, &key, total);
+    Ok(total)
+}
+
+/// Đóng góp đã ghi trên chuỗi của `epoch`, theo địa chỉ.
+pub fn epoch_units(storage: &dyn ChainStore, epoch: u64) -> Vec<(String, u64)> {
+    let prefix = format!("{}units:", epoch_prefix(epoch));
+    storage.scan_state_data(&prefix).into_iter().map(|(k, v)| (k[prefix.len()..].to_string(), read_u64(Some(v)))).collect()
+}
+
+/// Phần web `share` của block `view.height` trả cho đóng góp của epoch trước; `None` nếu epoch trước không ai đóng góp.
+/// Mỗi block cộng `share` vào quỹ tích lũy của epoch đó, mỗi địa chỉ được hưởng phần quỹ tích lũy theo tỷ lệ đóng góp.
+/// Block trả cho tối đa MAX_PAYOUTS_PER_BLOCK địa chỉ đang được nợ nhiều nhất, trong giới hạn quỹ tích lũy chưa trả;
+/// phần chưa trả dồn sang block sau nên mọi worker lần lượt được trả, không chỉ nhóm đóng góp lớn nhất.
+/// Đầu mỗi epoch xóa dữ liệu của epoch e-2 (phần còn nợ của epoch đó không được trả nữa).
+pub fn settle(view: &mut StateView, share: u64) -> Option<BTreeMap<String, u64>> {
+    let epoch = epoch_of(view.height);
+    if view.height.is_multiple_of(EPOCH_BLOCKS) && epoch >= 2 {
+        for key in view.scan_data(&epoch_prefix(epoch - 2)).into_keys() { view.set_data(&key, None); }
+    }
+    let prefix = epoch_prefix(epoch.checked_sub(1)?);
+    let units_prefix = format!("{}units:", prefix);
+    let units: Vec<(String, u64)> = view.scan_data(&units_prefix).into_iter()
+        .map(|(k, v)| (k[units_prefix.len()..].to_string(), read_u64(Some(v))))
+        .collect();
+    let total: u128 = units.iter().map(|(_, u)| *u as u128).sum();
+    if total == 0 { return None; }
+
+    let accrued = read_u64(view.get_data(&format!("{}accrued", prefix))) + share;
+    let released = read_u64(view.get_data(&format!("{}released", prefix)));
+    write_u64(view, &format!("{}accrued", prefix), accrued);
+    let mut owed: Vec<(String, u64)> = units.into_iter().map(|(addr, u)| {
+        let entitled = (accrued as u128 * u as u128 / total) as u64;
+        let paid = read_u64(view.get_data(&format!("{}paid:{}", prefix, addr)));
+        (addr, entitled.saturating_sub(paid))
+    }).filter(|(_, debt)| *debt > 0).collect();
+    owed.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let mut budget = accrued - released;
+    let mut payouts = BTreeMap::new();
+    for (addr, debt) in owed.into_iter().take(MAX_PAYOUTS_PER_BLOCK) {
+        let amount = debt.min(budget);
+        if amount == 0 { break; }
+        budget -= amount;
+        let key = format!("{}paid:{}", prefix, addr);
+        let paid = read_u64(view.get_data(&key)) + amount;
+        write_u64(view, &key, paid);
+        payouts.insert(addr, amount);
+    }
+    write_u64(view, &format!("{}released", prefix), accrued - budget);
+    Some(payouts)
+}
+
+/// SNN con cho web worker: 32 neuron, đầu vào mỗi bước = SHA256(seed:step).
+/// Chỉ dùng số nguyên để trình duyệt (JS) tính ra đúng cùng kết quả. Trả về tổng số xung.
+pub fn snn_subtask(seed: &str, steps: u32) -> u64 {
+    let sensitivity: Vec<i64> = Sha256::digest(seed.as_bytes()).iter().map(|b| (*b as i64 % 8) + 1).collect();
+    let mut potential = [0i64; 32];
+    let mut spikes = 0;
+    for step in 0..steps {
+        let input = Sha256::digest(format!("{}:{}", seed, step).as_bytes());
+        for i in 0..32 {
+            potential[i] += (input[i] as i64 - 128) * sensitivity[i];
+            if potential[i] >= SUBTASK_THRESHOLD { potential[i] = 0; spikes += 1; } else { potential[i] -= potential[i] / 16; }
+        }
+    }
+    spikes
+}
+
+/// Thử thách node ký bằng khóa Dilithium (kiểm tra qua /api/identity), worker trả lời bằng `snn_subtask(seed, steps)`
+/// với `seed = webwork_seed(epoch, worker, anchor, index)`. Lời giải đúng được trả về dạng payload WebWork để worker
+/// (hoặc ví chuyển tiếp) tự ký và trả phí; chuỗi tự kiểm lại seed từ block neo nên không cần tin node phát thử thách.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Challenge {
+    pub id: String,
+    pub client_id: String,
+    pub epoch: u64,
+    pub worker: String,
+    pub anchor: String,
+    pub index: u32,
+    pub seed: String,
+    pub steps: u32,
+    pub expires_at: u64,
+    pub signature: String,
+}
+
+impl Challenge {
+    fn payload(&self) -> Vec<u8> {
+        format!("{}:{}:{}:{}:{}:{}:{}:{}:{}", self.id, self.client_id, self.epoch, self.worker, self.anchor, self.index, self.seed, self.steps, self.expires_at).into_bytes()
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct WebWorker {
@@ -10,31 +202,202 @@ pub struct WebWorker {
 
 pub struct WebNodeManager {
     workers: RwLock<HashMap<String, WebWorker>>,
+    // client_id -> địa chỉ ví nhận thưởng
+    addresses: RwLock<HashMap<String, String>>,
+    // client_id -> thử thách đang chờ trả lời (mỗi worker một cái)
+    challenges: RwLock<HashMap<String, Challenge>>,
+    // địa chỉ nhận thưởng -> (epoch, chỉ số bằng chứng kế tiếp sẽ phát)
+    next_index: RwLock<HashMap<String, (u64, u32)>>,
+    storage: Arc<dyn ChainStore>,
+    wallet: Arc<QuantumWallet>,
+    height: Arc<RwLock<u64>>,
 }
 
 impl WebNodeManager {
-    pub fn new() -> Self {
+    pub fn new(storage: Arc<dyn ChainStore>, wallet: Arc<QuantumWallet>, height: Arc<RwLock<u64>>) -> Self {
         Self {
             workers: RwLock::new(HashMap::new()),
+            addresses: RwLock::new(HashMap::new()),
+            challenges: RwLock::new(HashMap::new()),
+            next_index: RwLock::new(HashMap::new()),
+            storage,
+            wallet,
+            height,
         }
     }
 
     pub async fn register_beat(&self, client_id: String, hashrate: f32) {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let now = now_secs();
         let mut w = self.workers.write().await;
         w.insert(client_id, WebWorker { last_seen: now, hashrate });
     }
 
-    pub async fn get_stats(&self) -> (usize, f32) {
+    /// Gắn worker với địa chỉ ví nhận thưởng ("PAPPAP" + 32 ký tự hex viết hoa).
+    pub async fn register(&self, client_id: String, address: String) -> Result<(), String> {
+        if !is_valid_address(&address) {
+            return Err("Địa chỉ ví không hợp lệ".to_string());
+        }
+        self.addresses.write().await.insert(client_id, address);
+        Ok(())
+    }
+
+    /// Phát bằng chứng kế tiếp chưa dùng trên chuỗi của địa chỉ đã đăng ký, trong epoch của block đỉnh.
+    pub async fn issue_challenge(&self, client_id: String) -> Result<Challenge, String> {
+        let worker = self.addresses.read().await.get(&client_id).cloned().ok_or("Worker chưa đăng ký địa chỉ ví")?;
+        let epoch = epoch_of(*self.height.read().await);
+        let anchor = epoch_anchor(&*self.storage, epoch).ok_or(format!("Epoch {} chưa có block neo", epoch))?;
+        let (index, seed) = {
+            let mut next = self.next_index.write().await;
+            let entry = next.entry(worker.clone()).or_insert((epoch, 0));
+            if entry.0 != epoch { *entry = (epoch, 0); }
+            loop {
+                if entry.1 >= MAX_PROOFS_PER_EPOCH {
+                    return Err(format!("{} đã nhận đủ {} bằng chứng của epoch {}", worker, MAX_PROOFS_PER_EPOCH, epoch));
+                }
+                let seed = webwork_seed(epoch, &worker, &anchor, entry.1);
+                entry.1 += 1;
+                if self.storage.get_state_data(&seed_used(epoch, &seed)).is_none() { break (entry.1 - 1, seed); }
+            }
+        };
+        let mut id = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut id);
+        let mut challenge = Challenge {
+            id: hex::encode(id),
+            client_id: client_id.clone(),
+            epoch,
+            worker,
+            anchor,
+            index,
+            seed,
+            steps: WEBWORK_STEPS,
+            expires_at: now_secs() + CHALLENGE_TTL_SECS,
+            signature: String::new(),
+        };
+        challenge.signature = hex::encode(self.wallet.sign_data(&challenge.payload()).await);
+        self.challenges.write().await.insert(client_id, challenge.clone());
+        Ok(challenge)
+    }
+
+    /// Kiểm tra lời giải; đúng thì trả về payload WebWork để worker tự ký, trả phí và gửi qua /api/tx/submit
+    /// (đóng góp chỉ được tính khi giao dịch vào block).
+    pub async fn submit_answer(&self, client_id: &str, challenge_id: &str, answer: u64) -> Result<TxPayload, String> {
+        let challenge = {
+            let mut pending = self.challenges.write().await;
+            match pending.get(client_id) {
+                Some(c) if c.id == challenge_id => pending.remove(client_id).unwrap(),
+                _ => return Err("Không có thử thách này".to_string()),
+            }
+        };
+        if challenge.expires_at < now_secs() {
+            return Err("Thử thách đã hết hạn".to_string());
+        }
+        let (seed, steps) = (challenge.seed.clone(), challenge.steps);
+        let expected = tokio::task::spawn_blocking(move || snn_subtask(&seed, steps)).await.unwrap();
+        if answer != expected {
+            return Err("Sai lời giải".to_string());
+        }
+        Ok(TxPayload::WebWork { epoch: challenge.epoch, worker: challenge.worker, index: challenge.index, answer })
+    }
+
+    /// (số worker online, tổng đóng góp đã xác minh trong epoch hiện tại, hashrate tự khai chưa kiểm chứng).
+    pub async fn get_stats(&self) -> (usize, f32, f32) {
         let w = self.workers.read().await;
-        let count = w.len();
-        let total_power: f32 = w.values().map(|v| v.hashrate).sum();
-        (count, total_power)
+        let epoch = epoch_of(*self.height.read().await);
+        let verified: u64 = epoch_units(&*self.storage, epoch).iter().map(|(_, units)| units).sum();
+        (w.len(), verified as f32, w.values().map(|v| v.hashrate).sum())
     }
 
     pub async fn prune_offline(&self) {
-        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let now = now_secs();
         let mut w = self.workers.write().await;
         w.retain(|_, v| now - v.last_seen < 15);
+        self.challenges.write().await.retain(|_, c| c.expires_at >= now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::governance::GovernanceParams;
+    use crate::memstore::MemoryStore;
+
+    fn worker(i: u64) -> String {
+        format!("PAPPAP{:032X}", i)
+    }
+
+    fn solve(view: &StateView, epoch: u64, worker: &str, index: u32) -> u64 {
+        let anchor = String::from_utf8(view.get_data(&anchor_key(epoch)).unwrap()).unwrap();
+        snn_subtask(&webwork_seed(epoch, worker, &anchor, index), WEBWORK_STEPS)
+    }
+
+    #[test]
+    fn proof_is_bound_to_epoch_anchor_and_used_once() {
+        let storage = MemoryStore::new();
+        let governance = GovernanceParams::default();
+        let mut view = StateView::new(&storage, 1, &governance);
+        assert!(record_work(&mut view, 0, &worker(1), 0, 0).unwrap_err().contains("block neo"));
+
+        anchor_epoch(&mut view, "aa");
+        view.height = 2;
+        anchor_epoch(&mut view, "bb");
+        let answer = solve(&view, 0, &worker(1), 0);
+        // Block neo đã ghi không bị block sau trong epoch đè
+        assert_eq!(view.get_data(&anchor_key(0)), Some(b"aa".to_vec()));
+        assert!(record_work(&mut view, 0, &worker(1), 0, answer + 1).is_err());
+        // Seed đổi theo địa chỉ, chỉ số và block neo
+        assert_ne!(webwork_seed(0, &worker(1), "aa", 0), webwork_seed(0, &worker(2), "aa", 0));
+        assert_ne!(webwork_seed(0, &worker(1), "aa", 0), webwork_seed(0, &worker(1), "aa", 1));
+        assert_ne!(webwork_seed(0, &worker(1), "aa", 0), webwork_seed(0, &worker(1), "bb", 0));
+        assert!(record_work(&mut view, 0, &worker(1), MAX_PROOFS_PER_EPOCH, answer).unwrap_err().contains("giới hạn"));
+        assert_eq!(record_work(&mut view, 0, &worker(1), 0, answer), Ok(WEBWORK_STEPS as u64));
+        assert_eq!(record_work(&mut view, 0, &worker(1), 0, answer), Err("Bằng chứng công đã được dùng".to_string()));
+    }
+
+    #[test]
+    fn proofs_expire_after_next_epoch_and_units_are_capped() {
+        let storage = MemoryStore::new();
+        let governance = GovernanceParams::default();
+        let mut view = StateView::new(&storage, 1, &governance);
+        anchor_epoch(&mut view, "aa");
+        view.height = EPOCH_BLOCKS;
+        anchor_epoch(&mut view, "bb");
+
+        // Bằng chứng epoch 0 còn nhận ở epoch 1 nhưng cộng vào đóng góp epoch 1, trần tính cả hai
+        let cap = MAX_PROOFS_PER_EPOCH as u64 * WEBWORK_STEPS as u64;
+        write_u64(&mut view, &format!("{}units:{}", epoch_prefix(1), worker(1)), cap - WEBWORK_STEPS as u64);
+        let last = solve(&view, 0, &worker(1), 5);
+        assert_eq!(record_work(&mut view, 0, &worker(1), 5, last), Ok(cap));
+        let over = solve(&view, 1, &worker(1), 0);
+        assert!(record_work(&mut view, 1, &worker(1), 0, over).unwrap_err().contains("trần"));
+
+        view.height = 2 * EPOCH_BLOCKS;
+        let late = solve(&view, 0, &worker(2), 0);
+        assert!(record_work(&mut view, 0, &worker(2), 0, late).unwrap_err().contains("hết hạn"));
+    }
+
+    #[test]
+    fn settle_carries_unpaid_share_to_next_block() {
+        let storage = MemoryStore::new();
+        let governance = GovernanceParams::default();
+        let mut view = StateView::new(&storage, EPOCH_BLOCKS - 1, &governance);
+        let workers = MAX_PAYOUTS_PER_BLOCK as u64 + 4;
+        for i in 0..workers { write_u64(&mut view, &format!("{}units:{}", epoch_prefix(0), worker(i)), WEBWORK_STEPS as u64); }
+        assert_eq!(settle(&mut view, 2_000), None);
+
+        view.height = EPOCH_BLOCKS;
+        let first = settle(&mut view, 2_000).unwrap();
+        assert_eq!(first.len(), MAX_PAYOUTS_PER_BLOCK);
+        assert!(first.values().all(|amount| *amount == 100));
+
+        // Block sau ưu tiên 4 worker chưa được trả; 4 worker còn lại chờ block kế tiếp
+        view.height += 1;
+        let second = settle(&mut view, 2_000).unwrap();
+        assert_eq!(second.len(), MAX_PAYOUTS_PER_BLOCK);
+        for i in MAX_PAYOUTS_PER_BLOCK as u64..workers { assert_eq!(second.get(&worker(i)), Some(&200)); }
+        view.height += 1;
+        let third = settle(&mut view, 2_000).unwrap();
+        for i in 12..MAX_PAYOUTS_PER_BLOCK as u64 { assert_eq!(third.get(&worker(i)), Some(&200)); }
+        let paid: u64 = first.values().chain(second.values()).chain(third.values()).sum();
+        assert_eq!(paid, 5_600);
     }
 }